@@ -0,0 +1,4933 @@
+//! Procedural macro of [serde_flat_regex](https://docs.rs/serde_flat_regex), use it through that crate.
+
+#![deny(missing_docs, unused_imports)]
+// the `FromField` derive of darling 0.14 emits a redundant `continue`, which can't be allowed on the struct
+#![allow(clippy::needless_continue)]
+
+extern crate proc_macro;
+extern crate quote;
+extern crate syn;
+
+use darling::{util::Flag, FromField};
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
+use proc_macro_error::{abort, abort_call_site, proc_macro_error};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote,
+    punctuated::Punctuated,
+    AngleBracketedGenericArguments, Attribute, Expr, Field, Fields, GenericArgument, Item,
+    Lifetime, Meta, Path, PathArguments, Token, Type, TypeReference,
+};
+
+/// Macro for flattening map types with regex maching keys.
+///
+/// **Important**: The macro must be placed **before** deriving Deserialize
+///
+/// The private helper functions for serde are emitted right next to the item, so the item can be declared
+/// anywhere, e.g. in a function body or by a `macro_rules!` macro.
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use std::str::Utf8Error;
+/// use std::ffi::CString;
+/// use serde_flat_regex::flat_regex;
+/// use serde::Deserialize;
+///
+/// #[flat_regex]
+/// #[derive(Debug,Deserialize)]   
+/// struct RouterStatus {
+///     online: bool,
+///     #[flat_regex(regex = r"lanportstatus_\d+")]
+///     lanport_status: BTreeMap<String,bool>,
+/// }
+/// ```
+///
+/// # Field options
+///
+/// The options are given on the field, `#[flat_regex(regex = r"...", ...)]`, or on the item naming the field,
+/// `#[flat_regex(field = "lanport_status", regex = r"...")]`, e.g. if the fields are generated by another macro.
+/// On an enum variant the item form only applies to the field of this variant.
+/// A `#[serde(transparent)]` newtype like `struct Ports(#[flat_regex(regex = "...")] HashMap<String, bool>)`
+/// deserializes from a map with all keys, keeping the matching ones.
+///
+/// ## Patterns
+///
+/// | Option | Keys taken by the field |
+/// |---|---|
+/// | `regex = r"..."` | Keys matching the regex, validated at compile time. An expression like `concat!(env!("PREFIX"), r"_\d+")` is compiled at runtime, an invalid one is a deserialization error. |
+/// | `glob = "port_*"` | Whole keys matching the glob with `*`, `?` and `[0-9]` or `[!0-9]`. |
+/// | `regex_include = "port.re"` | The regex in the file, relative to your `Cargo.toml` and trimmed. |
+/// | `literals = ["lan_", "wan_"]` | Keys containing any literal, matched with Aho-Corasick. Needs the `aho-corasick` feature. |
+/// | `matcher = "fn_name"` | Keys for which `fn(key: &str) -> bool` returns `true`. |
+/// | `routes = [r"...", ...]` | Partitions the keys into a tuple of collections by the first matching regex, without other options. |
+/// | `rest` | Keys not taken by any other `flat_regex` field of the item, matched as the field sees them, e.g. with `auto_prefix` stripped. |
+/// | `unmatched` | Keys the pattern doesn't match, the keys the field would skip; `overflow` moves them to a sibling. |
+/// | `prefix = "dev_"`, `suffix = "_up"` | Keys starting and ending with the literals whose rest matches the whole `regex`. |
+/// | `ascii_case_insensitive` | Matches ASCII letters case insensitive, without Unicode support. |
+/// | `ci_literals` | Matches only the literal parts of the regex ASCII case insensitive. |
+/// | `word_boundary` | Matches whole words, any character besides letters and digits separates words. |
+/// | `deny_leading_wildcard` | Rejects a literal regex starting with `.*` or `.+`. |
+/// | `size_limit = n`, `dfa_size_limit = n` | Builds the regex with these limits, checked at compile time for literals. |
+/// | `precompile` | Embeds a DFA built by the macro instead of compiling on first use, without capture groups. Needs the `precompile` feature. |
+///
+/// ## Keys
+///
+/// Keys are matched through `AsRef<str>`, integer keys by their decimal string.
+///
+/// | Option | |
+/// |---|---|
+/// | `key_access = "fn_name"` | Matches `fn(key: &K) -> Result<&str, E>`, a list of functions is tried in order. |
+/// | `key_access_owned = "fn_name"` | Matches `fn(key: K) -> String` of a clone of the key. |
+/// | `lossy_utf8` | Matches byte string keys with `String::from_utf8_lossy`. |
+/// | `borrow_key` | Matches keys borrowed from the input and only deserializes the matching ones. |
+/// | `key_parse` | Parses the matched key with `FromStr` and serializes it with `ToString`. |
+/// | `key_wrap = "fn_name"` | Stores `fn_name(key)` of the `String` key. |
+/// | `replace = "$idx"` | Stores the key rewritten by `Regex::replace`. |
+/// | `auto_prefix` | Takes keys starting with the item name in snake case or lowercase and `_`, with the prefix stripped. |
+/// | `urldecode` | Matches and stores the percent-decoded key, `keep_encoded_key` stores the original. Needs the `urldecode` feature. |
+/// | `dedup_key = "fn_name"` | Skips keys whose `fn(key: &str) -> String` was seen before. |
+/// | `stop_key = "__end__"` | Takes no keys from the sentinel on. |
+/// | `only` | Fails on the first key neither the field nor another `flat_regex` field takes instead of skipping it. |
+/// | `trace_skipped` | Logs skipped keys with `tracing::trace!`. Needs the `tracing` feature. |
+///
+/// ## Values
+///
+/// | Option | |
+/// |---|---|
+/// | `value_variant = "fn_name"` | Deserializes an enum value as the variant named by `fn(key: &str) -> &'static str`. |
+/// | `value_into` | Deserializes values as `String` and converts them with `Into`. |
+/// | `value_as = "T"`, `value_convert = "fn_name"` | Deserializes values as `T` and converts them with `fn(T) -> Result<V, E>`. |
+/// | `value_serde_as = "DisplayFromStr"` | Deserializes values with a `DeserializeAs`. Needs the `serde_with` feature. |
+/// | `value_flat_regex = r"..."` | Keeps the entries of map values whose keys match. |
+/// | `key_from_capture = "k"`, `value_from_capture = "v"` | Parses key and value from capture groups of the key. |
+/// | `captures_field = "name"` | Fills the sibling `HashMap<String, Vec<String>>` with the capture groups of each key. |
+/// | `overflow = "name"` | Fills the sibling with the keys the field skips, even if another field takes them. |
+/// | `on_error = "skip"` | Skips entries whose value fails to deserialize, not for `#[serde(transparent)]` newtypes. |
+///
+/// ## Collections
+///
+/// The field is a map type implementing `Extend<(K, V)> + Default`, e.g. `HashMap<K, V, S>`, `Vec<(K, V)>` in input
+/// order or `SmallVec<[(K, V); N]>` with the `smallvec` feature. An `Option` of it is `None` for `null`.
+///
+/// | Option | |
+/// |---|---|
+/// | `array`, `key_from_capture = "idx"` | Places the values in `[V; N]` by the captured index, `allow_missing` fills missing ones with `V::default()`. |
+/// | `into_indexed_vec`, `key_from_capture = "idx"` | Places the values in `Vec<V>` by the captured index, up to 1MiB or `max_index = n`. |
+/// | `contiguous` | Fails if a captured index from `0` to the largest one is missing. |
+/// | `multimap` | Collects the values of the same key in `HashMap<K, Vec<V>>`. |
+/// | `invert` | Stores entries as `(value, key)`. |
+/// | `on_collision = "error"` | Fails if a key is stored twice, e.g. after `replace`. Needs a map like `HashMap`, not `Vec<(K, V)>`. |
+/// | `take = n` | Stores only the first `n` matches. |
+/// | `reserve_hint` | Reserves space from `MapAccess::size_hint`, for collections with `reserve` like `HashMap`, not `BTreeMap`. |
+/// | `wrap = "Arc::new"` | Passes the collection to the function. |
+/// | `collect_into = "T"`, `try_collect_into = "T"` | Collects into `T` and converts it with `From` or `TryFrom`. |
+/// | `empty_is_none` | An `Option` field without matching keys is `None`. |
+/// | `allow_null`, `map_or_empty_seq` | Deserializes `null` or `[]` to an empty collection. |
+/// | `auto_borrow` | Adds `#[serde(borrow)]` for borrowed keys or values. |
+///
+/// ## Generated code
+///
+/// | Option | |
+/// |---|---|
+/// | `serialize_matching` | Only serializes the matching entries, the default for items only deriving `Serialize`. |
+/// | `into_flat` | Generates `{field}_into_flat(&self)` returning a `serde_json::Map`. Needs the `json` feature. |
+/// | `export_pattern` | Generates an associated const like `LANPORT_STATUS_PATTERN`. |
+/// | `doc` | Generates a doc comment describing the keys. |
+/// | `cfg_feature = "name"` | Only flattens if the feature `name` of your crate is enabled. |
+///
+/// Structs with `flat_regex` fields get the associated const `FLAT_REGEX_PATTERNS: &[(&str, Option<&str>)]` with the
+/// name and pattern of each field, so they can't declare a const of that name themselves. The pattern is `None` if
+/// it's only known at runtime, for a `regex` expression other than `concat!`, `stringify!`, `env!` or
+/// `include_str!`, or if no pattern describes the keys, for `matcher`, `rest` and `unmatched` fields.
+///
+/// # Item options
+///
+/// | Option | |
+/// |---|---|
+/// | `base = r"^device_"` | Prepends the regex to the `regex` of every field. |
+/// | `gen_try_from_value` | Generates `TryFrom<serde_json::Value>`. Needs the `json` feature. |
+/// | `gen_merge_from_value` | Generates `merge_from_value(&mut self, value)` extending the fields in place. Needs the `json` feature. |
+/// | `track_errors` | Names the failed field and its pattern in the error, which `from_value_strict` returns as `Error::Field`. |
+/// | `crate = "path"` | Refers to the crate by `path` in the generated code, e.g. if it's renamed. |
+///
+/// # Arrays
+///
+/// ```
+/// use serde_flat_regex::flat_regex;
+/// use serde::Deserialize;
+///
+/// #[flat_regex]
+/// #[derive(Debug,Deserialize)]
+/// struct RouterStatus {
+///     #[flat_regex(regex = r"^lanportstatus_(?P<idx>\d+)$", array, key_from_capture = "idx")]
+///     lanport_status: [bool; 2],
+/// }
+///
+/// let json = serde_json::json!({
+///     "lanportstatus_1": false,
+///     "lanportstatus_0": true,
+/// });
+/// let res: RouterStatus = serde_json::from_value(json).unwrap();
+/// assert_eq!(res.lanport_status, [true, false])
+/// ```
+///
+/// # Serialization
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// use serde_flat_regex::flat_regex;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[flat_regex]
+/// #[derive(Debug,Deserialize,Serialize)]
+/// struct RouterStatus {
+///     online: bool,
+///     #[flat_regex(regex = r"lanportstatus_\d+", serialize_matching)]
+///     lanport_status: BTreeMap<String,bool>,
+/// }
+///
+/// let status = RouterStatus {
+///     online: true,
+///     lanport_status: BTreeMap::from([
+///         ("lanportstatus_0".to_string(), true),
+///         ("lanportspeed_0".to_string(), false), // no maching key will not be serialized
+///     ]),
+/// };
+/// let json = serde_json::to_value(&status).unwrap();
+/// assert_eq!(json, serde_json::json!({"online": true, "lanportstatus_0": true}))
+/// ```
+///
+/// # Formats
+///
+/// The generated code only uses the serde data model and is tested with JSON, BSON, TOML and YAML. Flattened maps
+/// are buffered by serde, so keys have to be strings or deserializable from strings.
+#[proc_macro_error]
+#[proc_macro_attribute]
+pub fn flat_regex(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args with Punctuated::<FlatArg, Token![,]>::parse_terminated);
+    let mut item = parse_macro_input!(input as Item);
+    let item_args = apply_item_args(args, &mut item);
+    apply_variant_args(&mut item);
+    apply_sibling_fields(&mut item);
+
+    if let syn::Item::Struct(ref s) = item {
+        let att = &s.attrs;
+        let fields = &s.fields;
+        let vis = &s.vis;
+        let name = &s.ident;
+        let gen = &s.generics;
+
+        let container = SerdeContainer::from_attrs(att);
+        let mut generated = Generated::default();
+        let fields = fields
+            .iter()
+            .map(|f| {
+                replace_attr(
+                    f,
+                    &name.to_string(),
+                    &name.to_string(),
+                    &container,
+                    &item_args,
+                    &mut generated,
+                )
+            })
+            .collect::<Vec<_>>();
+        let with_fn = &generated.with_fn;
+        let patterns = generated.patterns_const();
+        let methods = &generated.methods;
+        let rest_patterns_fns = generated.rest_patterns_fns(&item_args.krate());
+        let (impl_gen, ty_gen, where_clause) = gen.split_for_impl();
+        let try_from_value = item_args.try_from_value_impl(name, gen);
+        let merge_from_value = item_args.merge_from_value_method(&generated.merges);
+        let body = match &s.fields {
+            Fields::Named(_) => quote!(#where_clause { #(#fields,)* }),
+            Fields::Unnamed(_) => quote!(( #(#fields,)* ) #where_clause;),
+            Fields::Unit => quote!(#where_clause;),
+        };
+        quote!(
+                #(#att)*
+                #vis struct #name #gen #body
+
+        impl #impl_gen #name #ty_gen #where_clause {
+            #patterns
+            #(#methods)*
+            #merge_from_value
+        }
+
+        #try_from_value
+        #(#with_fn)*
+        #rest_patterns_fns
+        )
+        .into()
+    } else if let syn::Item::Enum(ref en) = item {
+        let mut generated = Generated::default();
+        let name = &en.ident;
+        let attrs = &en.attrs;
+        let gen = &en.generics;
+        let vis = &en.vis;
+        let container = SerdeContainer::from_attrs(attrs);
+
+        let variants = en.variants.iter().map(|v| {
+            let var_name = &v.ident;
+            let att = &v.attrs;
+
+            if let Fields::Named(a) = &v.fields {
+                let fields = a.named.iter().map(|f| {
+                    replace_attr(
+                        f,
+                        &format!("{name}_{var_name}"),
+                        &format!("{name}::{var_name}"),
+                        &container,
+                        &item_args,
+                        &mut generated,
+                    )
+                });
+                quote!(
+                        #(#att)*
+                        #var_name {
+                            #(#fields),*
+                        }
+                )
+            } else {
+                quote!(#v)
+            }
+        });
+        let variants = variants.collect::<Vec<_>>();
+        if !generated.methods.is_empty() {
+            abort_call_site!(
+                "`into_flat` and `export_pattern` are only supported on struct fields"
+            );
+        }
+        if item_args.gen_merge_from_value {
+            abort_call_site!("`gen_merge_from_value` is only supported on structs");
+        }
+        let with_fn_vec = &generated.with_fn;
+        let rest_patterns_fns = generated.rest_patterns_fns(&item_args.krate());
+        let try_from_value = item_args.try_from_value_impl(name, gen);
+        quote!(
+            #(#attrs)*
+            #vis enum #name #gen {
+            #(#variants),*
+            }
+
+            #try_from_value
+            #(#with_fn_vec)*
+            #rest_patterns_fns
+        )
+        .into()
+    } else {
+        abort!(item, "only struct and enum supported")
+    }
+}
+
+/// The arguments of the item attribute which apply to the whole item.
+#[derive(Default)]
+struct ItemArgs {
+    /// `base = "..."`, prepended to the `regex` of every field.
+    base: Option<syn::LitStr>,
+    /// `crate = "..."`, the path of this crate in the generated code if it's renamed or re-exported.
+    krate: Option<syn::Path>,
+    /// `gen_try_from_value`, generates `TryFrom<serde_json::Value>` for the item.
+    gen_try_from_value: bool,
+    /// `gen_merge_from_value`, generates the method `merge_from_value` for the struct.
+    gen_merge_from_value: bool,
+    /// `track_errors`, names the failed field in the error for `from_value_strict`.
+    track_errors: bool,
+}
+
+impl ItemArgs {
+    /// The path of this crate, `serde_flat_regex` unless set with `crate = "..."`.
+    fn krate(&self) -> syn::Path {
+        self.krate
+            .clone()
+            .unwrap_or_else(|| syn::parse_quote!(serde_flat_regex))
+    }
+
+    /// Wraps the body of a deserialize helper to name its field in the error with `track_errors`.
+    fn track_field(
+        &self,
+        field_path: &str,
+        pattern: &proc_macro2::TokenStream,
+        body: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        if !self.track_errors {
+            return body.clone();
+        }
+        let krate = self.krate();
+        quote!(#krate::__track_flat_field(#field_path, #pattern, move || #body))
+    }
+
+    /// Generates `impl TryFrom<serde_json::Value>` with `gen_try_from_value`.
+    fn try_from_value_impl(&self, name: &Ident, gen: &syn::Generics) -> proc_macro2::TokenStream {
+        if !self.gen_try_from_value {
+            return quote!();
+        }
+        let (impl_gen, ty_gen, where_clause) = gen.split_for_impl();
+        quote!(
+            impl #impl_gen std::convert::TryFrom<serde_json::Value> for #name #ty_gen #where_clause {
+                type Error = serde_json::Error;
+
+                fn try_from(value: serde_json::Value) -> std::result::Result<Self, Self::Error> {
+                    serde_json::from_value(value)
+                }
+            }
+        )
+    }
+    /// Generates the method `merge_from_value` with `gen_merge_from_value`.
+    fn merge_from_value_method(
+        &self,
+        merges: &[(Option<syn::LitStr>, proc_macro2::TokenStream)],
+    ) -> proc_macro2::TokenStream {
+        if !self.gen_merge_from_value {
+            return quote!();
+        }
+        if merges.is_empty() {
+            abort_call_site!(
+                "`gen_merge_from_value` needs a field which can be extended in place, e.g. a `HashMap` without `wrap`"
+            );
+        }
+        // the value is unused if every field is behind a disabled `cfg_feature`
+        let allow_unused = merges
+            .iter()
+            .map(|(feature, _)| feature.as_ref())
+            .collect::<Option<Vec<_>>>()
+            .map(|features| quote!(#[cfg_attr(not(any(#(feature = #features),*)), allow(unused_variables))]));
+        let merges = merges.iter().map(|(feature, merge)| match feature {
+            Some(feature) => quote!(#[cfg(feature = #feature)] #merge),
+            None => merge.clone(),
+        });
+        quote!(
+            /// Extends the flat_regex fields with the matching entries of `value`, keeping their existing entries.
+            ///
+            /// Entries of the value replace existing entries with the same key, the other fields are left as they are.
+            #allow_unused
+            pub fn merge_from_value(&mut self, value: serde_json::Value) -> std::result::Result<(), serde_json::Error> {
+                #(#merges)*
+                std::result::Result::Ok(())
+            }
+        )
+    }
+}
+
+/// Applies the arguments of the item attribute to the fields they target.
+///
+/// `#[flat_regex(field = "name", ...)]` on the item is the same as `#[flat_regex(...)]` on the named field(s).
+/// Returns the arguments applying to the whole item.
+fn apply_item_args(args: Punctuated<FlatArg, Token![,]>, item: &mut Item) -> ItemArgs {
+    let mut target = None;
+    let mut item_args = ItemArgs::default();
+    let mut field_args = Vec::new();
+    for arg in args {
+        match (arg.name.to_string().as_str(), &arg.value) {
+            ("track_errors", None) => item_args.track_errors = true,
+            ("track_errors", Some(_)) => abort!(arg.name, "`track_errors` takes no value"),
+            #[cfg(feature = "json")]
+            ("gen_try_from_value", None) => item_args.gen_try_from_value = true,
+            #[cfg(not(feature = "json"))]
+            ("gen_try_from_value", None) => {
+                abort!(arg.name, "`gen_try_from_value` needs the `json` feature")
+            }
+            ("gen_try_from_value", Some(_)) => {
+                abort!(arg.name, "`gen_try_from_value` takes no value")
+            }
+            #[cfg(feature = "json")]
+            ("gen_merge_from_value", None) => item_args.gen_merge_from_value = true,
+            #[cfg(not(feature = "json"))]
+            ("gen_merge_from_value", None) => {
+                abort!(arg.name, "`gen_merge_from_value` needs the `json` feature")
+            }
+            ("gen_merge_from_value", Some(_)) => {
+                abort!(arg.name, "`gen_merge_from_value` takes no value")
+            }
+            (
+                "base",
+                Some(Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })),
+            ) => item_args.base = Some(lit.clone()),
+            ("base", _) => abort!(arg.name, "`base` expects a string literal"),
+            (
+                "crate",
+                Some(Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })),
+            ) => match lit.parse() {
+                Ok(path) => item_args.krate = Some(path),
+                Err(e) => abort!(lit, "`crate` expects a path: {}", e),
+            },
+            ("crate", _) => abort!(arg.name, "`crate` expects a path as string"),
+            (
+                "field",
+                Some(Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })),
+            ) => target = Some(lit.clone()),
+            ("field", _) => abort!(arg.name, "`field` expects the name of a field as string"),
+            _ => field_args.push(arg),
+        }
+    }
+    let Some(target) = target else {
+        if let Some(arg) = field_args.first() {
+            abort!(
+                arg.name,
+                "field arguments on the item need a `field = \"name\"` to apply to"
+            );
+        }
+        return item_args;
+    };
+
+    let fields: Vec<&mut Field> = match item {
+        Item::Struct(s) => s.fields.iter_mut().collect(),
+        Item::Enum(en) => en
+            .variants
+            .iter_mut()
+            .flat_map(|v| v.fields.iter_mut())
+            .collect(),
+        _ => Vec::new(),
+    };
+    push_field_args(fields, &target, &field_args);
+    item_args
+}
+
+/// Adds `#[flat_regex(field_args)]` to the fields named `target`.
+fn push_field_args(fields: Vec<&mut Field>, target: &syn::LitStr, field_args: &[FlatArg]) {
+    let mut found = false;
+    for field in fields
+        .into_iter()
+        .filter(|f| matches!(&f.ident, Some(ident) if ident == &target.value()))
+    {
+        field
+            .attrs
+            .push(parse_quote!(#[flat_regex(#(#field_args),*)]));
+        found = true;
+    }
+    if !found {
+        abort!(target, "no field named `{}`", target.value());
+    }
+}
+
+/// Moves `#[flat_regex(field = "name", ...)]` on an enum variant to the field `name` of the variant.
+///
+/// Each variant can give its own pattern for a field with the same name.
+fn apply_variant_args(item: &mut Item) {
+    let Item::Enum(en) = item else {
+        return;
+    };
+    for variant in &mut en.variants {
+        let (attrs, other): (Vec<_>, Vec<_>) = variant.attrs.drain(..).partition(is_flat_regex);
+        variant.attrs = other;
+        for attr in attrs {
+            let args =
+                match attr.parse_args_with(Punctuated::<FlatArg, Token![,]>::parse_terminated) {
+                    Ok(args) => args,
+                    Err(e) => abort!(e.span(), e),
+                };
+            let (target, field_args): (Vec<_>, Vec<_>) =
+                args.into_iter().partition(|arg| arg.name == "field");
+            let Some(Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(target),
+                ..
+            })) = target.into_iter().next().and_then(|arg| arg.value)
+            else {
+                abort!(
+                    attr,
+                    "flat_regex on a variant needs a `field = \"name\"` to apply to"
+                )
+            };
+            push_field_args(variant.fields.iter_mut().collect(), &target, &field_args);
+        }
+    }
+}
+
+/// Moves `captures_field = "name"` of a field to its sibling `name` as `#[flat_regex(regex = <same regex>, captures)]`,
+/// and `overflow = "name"` as `#[flat_regex(regex = <same regex>, unmatched)]`.
+///
+/// The sibling sees the same keys as the field and collects the capture groups of its matches, or the keys it skips.
+fn apply_sibling_fields(item: &mut Item) {
+    let groups: Vec<Vec<&mut Field>> = match item {
+        Item::Struct(s) => vec![s.fields.iter_mut().collect()],
+        Item::Enum(en) => en
+            .variants
+            .iter_mut()
+            .map(|v| v.fields.iter_mut().collect())
+            .collect(),
+        _ => Vec::new(),
+    };
+    for mut fields in groups {
+        let mut targets = Vec::new();
+        for field in &mut fields {
+            for attr in field.attrs.iter_mut().filter(|a| is_flat_regex(a)) {
+                let args = match attr
+                    .parse_args_with(Punctuated::<FlatArg, Token![,]>::parse_terminated)
+                {
+                    Ok(args) => args,
+                    Err(e) => abort!(e.span(), e),
+                };
+                let (sibling_args, args): (Vec<_>, Vec<_>) = args
+                    .into_iter()
+                    .partition(|arg| arg.name == "captures_field" || arg.name == "overflow");
+                for sibling in sibling_args {
+                    let Some(Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(target),
+                        ..
+                    })) = sibling.value
+                    else {
+                        abort!(
+                            sibling.name,
+                            "`{}` expects the name of a field as string",
+                            sibling.name
+                        )
+                    };
+                    let Some(regex) = args.iter().find(|arg| arg.name == "regex") else {
+                        abort!(target, "`{}` needs a `regex`", sibling.name)
+                    };
+                    let sibling_args = if sibling.name == "overflow" {
+                        quote!(#regex, unmatched)
+                    } else {
+                        quote!(#regex, captures)
+                    };
+                    targets.push((target, sibling_args));
+                }
+                attr.tokens = quote!((#(#args),*));
+            }
+        }
+        for (target, sibling_args) in targets {
+            let Some(field) = fields
+                .iter_mut()
+                .find(|f| matches!(&f.ident, Some(ident) if ident == &target.value()))
+            else {
+                abort!(target, "no field named `{}`", target.value())
+            };
+            if field.attrs.iter().any(is_flat_regex) {
+                abort!(
+                    target,
+                    "the captures or overflow field can't have its own flat_regex attribute"
+                );
+            }
+            field.attrs.push(parse_quote!(#[flat_regex(#sibling_args)]));
+        }
+    }
+}
+
+/// Items generated for the `flat_regex` fields of a struct or enum.
+#[derive(Default)]
+struct Generated {
+    /// The `deserialize_with`/`serialize_with` helper functions.
+    with_fn: Vec<proc_macro2::TokenStream>,
+    /// `(field name, pattern)` pairs for `FLAT_REGEX_PATTERNS`, `None` for patterns only known at runtime.
+    patterns: Vec<proc_macro2::TokenStream>,
+    /// `(prefix, key transform, pattern)` of all fields, the siblings of `rest` fields.
+    prefix_patterns: Vec<(String, KeyTransform, proc_macro2::TokenStream)>,
+    /// `(prefix, key transform, matcher function)` of all fields with a `matcher`.
+    prefix_matchers: Vec<(String, KeyTransform, syn::ExprPath)>,
+    /// The prefixes with a `rest` or `only` field.
+    rest: Vec<String>,
+    /// Methods of the struct, like the `{field}_into_flat` methods.
+    methods: Vec<proc_macro2::TokenStream>,
+    /// Statements of `merge_from_value` extending a field with the entries of the value, with the `cfg_feature` of
+    /// the field.
+    merges: Vec<(Option<syn::LitStr>, proc_macro2::TokenStream)>,
+}
+
+impl Generated {
+    /// Generates the const `FLAT_REGEX_PATTERNS` of a struct with `flat_regex` fields.
+    fn patterns_const(&self) -> proc_macro2::TokenStream {
+        let patterns = &self.patterns;
+        if patterns.is_empty() {
+            return quote!();
+        }
+        quote!(
+            /// The field names of the `flat_regex` fields with their regex pattern.
+            // unused unless the struct is public or the const is read
+            #[allow(dead_code)]
+            pub const FLAT_REGEX_PATTERNS: &'static [(&'static str, std::option::Option<&'static str>)] =
+                &[#(#patterns),*];
+        )
+    }
+
+    /// Generates the functions listing the sibling patterns of `rest` fields and testing if a sibling takes a key.
+    ///
+    /// The patterns are compiled into the `RegexSet` of the `rest` field. The siblings match the key as they see it,
+    /// with `auto_prefix` stripped and with `urldecode` percent-decoded.
+    fn rest_patterns_fns(&self, krate: &syn::Path) -> proc_macro2::TokenStream {
+        let mut prefixes = self.rest.clone();
+        prefixes.sort();
+        prefixes.dedup();
+        let fns = prefixes.iter().map(|prefix| {
+            let fun = rest_patterns_fn(prefix);
+            let siblings_fun = rest_siblings_fn(prefix);
+            let siblings = self
+                .prefix_patterns
+                .iter()
+                .filter(|(p, ..)| p == prefix)
+                .collect::<Vec<_>>();
+            let patterns = siblings.iter().map(|(.., pattern)| pattern);
+            let plain = siblings
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, transform, _))| transform.is_identity())
+                .map(|(idx, _)| idx);
+            let plain = if siblings
+                .iter()
+                .all(|(_, transform, _)| transform.is_identity())
+            {
+                quote!(set.is_match(key))
+            } else {
+                quote!(set.matches(key).iter().any(|idx| [#(#plain),*].contains(&idx)))
+            };
+            let transformed = siblings
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, transform, _))| !transform.is_identity())
+                .map(|(idx, (_, transform, _))| {
+                    transform.apply(quote!(set.matches(key).matched(#idx)))
+                });
+            let matchers = self
+                .prefix_matchers
+                .iter()
+                .filter(|(p, ..)| p == prefix)
+                .map(|(_, transform, matcher)| transform.apply(quote!(#matcher(key))));
+            quote!(
+                fn #fun() -> std::vec::Vec<std::string::String> {
+                    std::vec![#(std::string::ToString::to_string(#patterns)),*]
+                }
+
+                fn #siblings_fun(set: &#krate::__regex::RegexSet, key: &str) -> bool {
+                    #plain #(|| #transformed)* #(|| #matchers)*
+                }
+            )
+        });
+        quote!(#(#fns)*)
+    }
+}
+
+/// How a field transforms a key before matching it, with `auto_prefix` and `urldecode`.
+#[derive(Default)]
+struct KeyTransform {
+    /// The prefixes stripped with `auto_prefix`, one of them is required.
+    auto_prefixes: Vec<String>,
+    /// The key is percent-decoded with `urldecode`.
+    urldecode: bool,
+}
+
+impl KeyTransform {
+    fn new(flat_field: &FlatRegex, owner: &str) -> Self {
+        KeyTransform {
+            auto_prefixes: if flat_field.auto_prefix.is_present() {
+                auto_prefixes(owner)
+            } else {
+                Vec::new()
+            },
+            urldecode: flat_field.urldecode.is_present(),
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.auto_prefixes.is_empty() && !self.urldecode
+    }
+
+    /// Wraps the condition `is_match` on `key: &str`, which is evaluated with the transformed key.
+    fn apply(&self, is_match: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let mut is_match = is_match;
+        if self.urldecode {
+            is_match = quote!({
+                let decoded_key = percent_encoding::percent_decode_str(key).decode_utf8_lossy();
+                let key: &str = &decoded_key;
+                #is_match
+            });
+        }
+        if !self.auto_prefixes.is_empty() {
+            let prefixes = &self.auto_prefixes;
+            is_match = quote!(
+                [#(#prefixes),*]
+                    .iter()
+                    .find_map(|prefix| key.strip_prefix(prefix))
+                    .is_some_and(|key| #is_match)
+            );
+        }
+        is_match
+    }
+}
+
+/// The serde traits derived by the annotated item and its serde container attributes,
+/// detected on a best-effort basis by scanning its `derive` and `serde` attributes.
+struct SerdeContainer {
+    serialize: bool,
+    deserialize: bool,
+    transparent: bool,
+}
+
+impl SerdeContainer {
+    fn from_attrs(attrs: &[Attribute]) -> Self {
+        let mut container = SerdeContainer {
+            serialize: false,
+            deserialize: false,
+            transparent: false,
+        };
+        for attr in attrs.iter().filter(|a| a.path.is_ident("derive")) {
+            let Ok(paths) = attr.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated)
+            else {
+                continue;
+            };
+            for path in paths {
+                let ident = &path.segments.last().unwrap().ident;
+                container.serialize |= ident == "Serialize";
+                container.deserialize |= ident == "Deserialize";
+            }
+        }
+        for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+            let Ok(metas) = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            else {
+                continue;
+            };
+            container.transparent |= metas.iter().any(|m| m.path().is_ident("transparent"));
+        }
+        container
+    }
+
+    /// Only `Serialize` is derived, deserialize helpers would be dead code.
+    fn serialize_only(&self) -> bool {
+        self.serialize && !self.deserialize
+    }
+}
+
+fn inner(ty: &syn::Type) -> (syn::GenericArgument, syn::GenericArgument) {
+    let ret = match ty {
+        syn::Type::Path(path) => {
+            if let PathArguments::AngleBracketed(AngleBracketedGenericArguments {
+                args: a, ..
+            }) = &path.path.segments.last().unwrap().arguments
+            {
+                // a third argument is the hasher of e.g. `HashMap<K, V, S>`
+                if a.len() == 2 || a.len() == 3 {
+                    (a[0].clone(), a[1].clone())
+                } else if let Some((key, value)) = pair(&a[0]) {
+                    // the entries of e.g. `Vec<(K, V)>`, extended in the order of the input
+                    (key, value)
+                } else {
+                    abort!(
+                        ty,
+                        "type signature needs two Types, a key Type and value Type"
+                    )
+                }
+            } else {
+                abort!(ty, "type signature needs two Types, a key and value Type")
+            }
+        }
+        _ => abort!(
+            ty,
+            "only angeled bracket types are supportet for flat_regex"
+        ),
+    };
+    ret
+}
+
+/// The key and value type of a `(K, V)` tuple argument, or of the `[(K, V); N]` array argument of `SmallVec`.
+fn pair(arg: &syn::GenericArgument) -> Option<(syn::GenericArgument, syn::GenericArgument)> {
+    match arg {
+        GenericArgument::Type(Type::Tuple(tuple)) if tuple.elems.len() == 2 => Some((
+            GenericArgument::Type(tuple.elems[0].clone()),
+            GenericArgument::Type(tuple.elems[1].clone()),
+        )),
+        GenericArgument::Type(Type::Array(array)) => {
+            let entries = pair(&GenericArgument::Type((*array.elem).clone()))?;
+            if !cfg!(feature = "smallvec") {
+                abort!(
+                    array,
+                    "`SmallVec<[(K, V); N]>` needs the `smallvec` feature"
+                );
+            }
+            Some(entries)
+        }
+        _ => None,
+    }
+}
+
+/// The collection is a sequence of entries like `Vec<(K, V)>` or `SmallVec<[(K, V); N]>` instead of a map.
+fn is_entry_seq(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(path) => matches!(
+            &path.path.segments.last().unwrap().arguments,
+            PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. })
+                if args.len() == 1 && pair(&args[0]).is_some()
+        ),
+        _ => false,
+    }
+}
+
+/// Rejects `reserve_hint` on the std collections without a `reserve` method.
+fn check_reserve_hint(flat_field: &FlatRegex, coll_ty: &syn::Type) {
+    let syn::Type::Path(path) = coll_ty else {
+        return;
+    };
+    let ident = &path.path.segments.last().unwrap().ident;
+    if flat_field.reserve_hint.is_present()
+        && ["BTreeMap", "BTreeSet", "LinkedList"].contains(&ident.to_string().as_str())
+    {
+        abort!(
+            ident,
+            "`reserve_hint` needs a collection with `reserve`, e.g. `HashMap` or `Vec`, `{}` can't reserve space",
+            ident
+        );
+    }
+}
+
+/// Rejects patterns starting with `.*` or `.+`, which scan the whole key on every match attempt.
+fn check_leading_wildcard(pattern: &syn::LitStr) {
+    let value = pattern.value();
+    let start = value.strip_prefix('^').unwrap_or(&value);
+    if start.starts_with(".*") || start.starts_with(".+") {
+        abort!(
+            pattern,
+            "the regex starts with the wildcard `{}`, which is denied by `deny_leading_wildcard`",
+            &start[..2]
+        );
+    }
+}
+
+/// Checks that the capture groups referenced by the `replace` template exist in the pattern.
+fn check_template(pattern: &syn::LitStr, template: &syn::LitStr) {
+    let re = regex::Regex::new(&pattern.value()).unwrap();
+    let refs = regex::Regex::new(r"\$(?:\$|\{([^}]*)\}|([0-9A-Za-z_]+))").unwrap();
+    for captures in refs.captures_iter(&template.value()) {
+        let Some(group) = captures.get(1).or_else(|| captures.get(2)) else {
+            continue;
+        };
+        let exists = match group.as_str().parse::<usize>() {
+            Ok(idx) => idx < re.captures_len(),
+            Err(_) => re.capture_names().any(|n| n == Some(group.as_str())),
+        };
+        if !exists {
+            abort!(
+                template,
+                "the regex has no capture group `{}`",
+                group.as_str()
+            );
+        }
+    }
+}
+
+/// The innermost type of single argument wrapper types, like `HashMap<K, V>` of `Arc<Mutex<HashMap<K, V>>>`.
+fn wrapped_inner(ty: &syn::Type) -> &syn::Type {
+    if let Type::Path(path) = ty {
+        if let PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) =
+            &path.path.segments.last().unwrap().arguments
+        {
+            if let (1, Some(GenericArgument::Type(inner))) = (args.len(), args.first()) {
+                return wrapped_inner(inner);
+            }
+        }
+    }
+    ty
+}
+
+/// The `T` of an `Option<T>` field type.
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last().unwrap();
+    match &segment.arguments {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. })
+            if segment.ident == "Option" && args.len() == 1 =>
+        {
+            match args.first() {
+                Some(GenericArgument::Type(inner)) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether the field already has `#[serde(borrow)]` or `#[serde(borrow = "...")]`.
+fn has_serde_borrow(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|a| {
+            a.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .any(|metas| metas.iter().any(|m| m.path().is_ident("borrow")))
+}
+
+/// Rejects attributes of `serde_with` and serde's `with` on a `flat_regex` field, which would set a second
+/// `deserialize_with` next to the generated one.
+///
+/// Depending on the order of the attributes, `#[serde_as]` on the item has either already rewritten
+/// `#[serde_as(as = "...")]` into `#[serde(with = "...")]` or will do so after this macro.
+fn check_serde_with(attrs: &[Attribute], container: &SerdeContainer) {
+    if let Some(attr) = attrs.iter().find(|a| a.path.is_ident("serde_as")) {
+        abort!(
+            attr,
+            "`#[serde_as]` conflicts with the `deserialize_with` of flat_regex, use `value_serde_as = \"...\"` for the values"
+        );
+    }
+    let conflicting = attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|a| {
+            a.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .find(|m| {
+            m.path().is_ident("with")
+                || (m.path().is_ident("deserialize_with") && !container.serialize_only())
+        });
+    if let Some(meta) = conflicting {
+        abort!(
+            meta,
+            "`#[serde({})]` conflicts with the `deserialize_with` of flat_regex, use `value_serde_as = \"...\"` for the values",
+            meta.path().to_token_stream()
+        );
+    }
+}
+
+/// Whether `ty` is one of the primitive integer types.
+fn is_integer(ty: &syn::GenericArgument) -> bool {
+    const INTEGERS: &[&str] = &[
+        "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+    ];
+    matches!(ty, GenericArgument::Type(Type::Path(path))
+        if path.qself.is_none() && INTEGERS.iter().any(|int| path.path.is_ident(int)))
+}
+
+/// Element type of a `Vec<V>` field used with `into_indexed_vec`.
+fn vec_inner(ty: &syn::Type) -> syn::GenericArgument {
+    if let Type::Path(path) = ty {
+        if let PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) =
+            &path.path.segments.last().unwrap().arguments
+        {
+            if args.len() == 1 {
+                return args[0].clone();
+            }
+        }
+    }
+    abort!(ty, "`into_indexed_vec` needs a `Vec<V>` field")
+}
+
+/// Binds `len` to `idx + 1` of a captured index, failing for indices above `max_index`.
+///
+/// Without `max_index` the `Vec<elem>` grown to the index, the entries of `into_indexed_vec` or the seen indices of
+/// `contiguous`, is limited to 1MiB, so a single key like `port_4000000000` can't make the visitor allocate gigabytes.
+fn index_len(
+    flat_field: &FlatRegex,
+    elem: impl ToTokens,
+    key: &proc_macro2::TokenStream,
+    field_name: &str,
+) -> proc_macro2::TokenStream {
+    let max_index = if let Some(max_index) = flat_field.max_index {
+        quote!(#max_index)
+    } else {
+        quote!((1024 * 1024 / std::cmp::max(std::mem::size_of::<#elem>(), 1)).saturating_sub(1))
+    };
+    quote!(
+        let max_index: usize = #max_index;
+        let len = match idx.checked_add(1) {
+            std::option::Option::Some(len) if idx <= max_index => len,
+            _ => {
+                return std::result::Result::Err(A::Error::custom(format!(
+                    "index {} of key `{}` exceeds the maximum index {} of `{}`",
+                    idx, #key, max_index, #field_name
+                )))
+            }
+        };
+    )
+}
+
+/// Element type and length of a `[V; N]` field used with `array`.
+fn array_inner(ty: &syn::Type) -> (syn::GenericArgument, syn::Expr) {
+    match ty {
+        Type::Array(array) => (
+            GenericArgument::Type((*array.elem).clone()),
+            array.len.clone(),
+        ),
+        _ => abort!(ty, "`array` needs a fixed-size array type `[V; N]`"),
+    }
+}
+
+fn lifetime(ty: &syn::GenericArgument) -> Option<Lifetime> {
+    match ty {
+        GenericArgument::Type(ty) => type_lifetime(ty),
+        GenericArgument::Lifetime(lt) => Some(lt.clone()),
+        _ => None,
+    }
+}
+
+/// The first lifetime in `ty`, also nested in generic arguments like the `'a` of `HashMap<&'a str, bool>`.
+fn type_lifetime(ty: &syn::Type) -> Option<Lifetime> {
+    match ty {
+        Type::Reference(TypeReference {
+            lifetime: Some(lt), ..
+        }) => Some(lt.clone()),
+        Type::Reference(TypeReference { elem, .. }) => type_lifetime(elem),
+        Type::Path(path) => {
+            path.path
+                .segments
+                .iter()
+                .find_map(|segment| match &segment.arguments {
+                    PathArguments::AngleBracketed(args) => args.args.iter().find_map(lifetime),
+                    _ => None,
+                })
+        }
+        _ => None,
+    }
+}
+
+#[derive(FromField)]
+#[darling(attributes(flat_regex))]
+struct FlatRegex {
+    ident: Option<syn::Ident>,
+    vis: syn::Visibility,
+    ty: syn::Type,
+    regex: Option<syn::LitStr>,
+    regex_include: Option<syn::LitStr>,
+    key_access: Option<syn::ExprPath>,
+    key_access_owned: Option<syn::ExprPath>,
+    serialize_matching: Flag,
+    array: Flag,
+    key_from_capture: Option<syn::LitStr>,
+    allow_missing: Flag,
+    borrow_key: Flag,
+    trace_skipped: Flag,
+    value_variant: Option<syn::ExprPath>,
+    reserve_hint: Flag,
+    rest: Flag,
+    allow_null: Flag,
+    matcher: Option<syn::ExprPath>,
+    wrap: Option<syn::ExprPath>,
+    key_parse: Flag,
+    replace: Option<syn::LitStr>,
+    map_or_empty_seq: Flag,
+    captures: Flag,
+    invert: Flag,
+    into_indexed_vec: Flag,
+    deny_leading_wildcard: Flag,
+    value_into: Flag,
+    on_error: Option<syn::LitStr>,
+    into_flat: Flag,
+    prefix: Option<syn::LitStr>,
+    suffix: Option<syn::LitStr>,
+    multimap: Flag,
+    take: Option<usize>,
+    ascii_case_insensitive: Flag,
+    ci_literals: Flag,
+    on_collision: Option<syn::LitStr>,
+    auto_prefix: Flag,
+    key_wrap: Option<syn::ExprPath>,
+    lossy_utf8: Flag,
+    cfg_feature: Option<syn::LitStr>,
+    glob: Option<syn::LitStr>,
+    stop_key: Option<syn::LitStr>,
+    word_boundary: Flag,
+    doc: Flag,
+    collect_into: Option<syn::Type>,
+    try_collect_into: Option<syn::Type>,
+    urldecode: Flag,
+    keep_encoded_key: Flag,
+    contiguous: Flag,
+    max_index: Option<usize>,
+    dedup_key: Option<syn::ExprPath>,
+    value_from_capture: Option<syn::LitStr>,
+    only: Flag,
+    size_limit: Option<usize>,
+    dfa_size_limit: Option<usize>,
+    export_pattern: Flag,
+    auto_borrow: Flag,
+    value_as: Option<syn::Type>,
+    value_convert: Option<syn::ExprPath>,
+    empty_is_none: Flag,
+    value_serde_as: Option<syn::Type>,
+    #[darling(rename = "value_flat_regex")]
+    value_pattern: Option<syn::LitStr>,
+    precompile: Flag,
+    unmatched: Flag,
+}
+
+/// The pattern of a `flat_regex` field.
+enum Pattern {
+    /// `regex = "..."`
+    Lit(syn::LitStr),
+    /// `regex = expr` evaluating to `&str`
+    Expr(Box<Expr>),
+    /// `prefix = "...", regex = "...", suffix = "..."`, the literals are stripped and the regex matches the rest
+    Affixed {
+        prefix: String,
+        middle: syn::LitStr,
+        suffix: String,
+    },
+    /// `literals = ["...", ...]` matched with an `aho_corasick::AhoCorasick` automaton
+    #[cfg(feature = "aho-corasick")]
+    Literals(Vec<syn::LitStr>),
+    /// `matcher = "fn_name"` with `fn fn_name(key: &str) -> bool`
+    Matcher(syn::ExprPath),
+    /// `rest`, matches keys not matching any sibling of the fields with the prefix
+    Rest(String),
+}
+
+impl ToTokens for Pattern {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        match self {
+            Pattern::Lit(lit) => lit.to_tokens(tokens),
+            Pattern::Expr(expr) => expr.to_tokens(tokens),
+            // matcher and rest fields have no pattern and are listed as `None` in `FLAT_REGEX_PATTERNS`
+            Pattern::Matcher(_) | Pattern::Rest(_) => {}
+            Pattern::Affixed { .. } => self.listed().to_tokens(tokens),
+            #[cfg(feature = "aho-corasick")]
+            Pattern::Literals(_) => self.listed().to_tokens(tokens),
+        }
+    }
+}
+
+impl Pattern {
+    /// The pattern is a const `&'static str` which can be listed in `FLAT_REGEX_PATTERNS`, otherwise it's listed as
+    /// `None`.
+    ///
+    /// `concat!`, `stringify!`, `env!` and `include_str!` expand to literals, other macros and expressions may
+    /// only be evaluated at runtime.
+    fn is_const(&self) -> bool {
+        match self {
+            Pattern::Expr(expr) => match &**expr {
+                Expr::Macro(mac) => mac.mac.path.segments.last().is_some_and(|segment| {
+                    ["concat", "stringify", "env", "include_str"]
+                        .contains(&segment.ident.to_string().as_str())
+                }),
+                _ => false,
+            },
+            _ => self.listed().is_some(),
+        }
+    }
+
+    /// The pattern as listed in `FLAT_REGEX_PATTERNS`, `None` for patterns only known at runtime.
+    fn listed(&self) -> Option<String> {
+        match self {
+            Pattern::Lit(lit) => Some(lit.value()),
+            Pattern::Affixed {
+                prefix,
+                middle,
+                suffix,
+            } => Some(format!(
+                "^{}(?:{}){}$",
+                regex::escape(prefix),
+                middle.value(),
+                regex::escape(suffix)
+            )),
+            #[cfg(feature = "aho-corasick")]
+            Pattern::Literals(lits) => Some(
+                lits.iter()
+                    .map(|l| regex::escape(&l.value()))
+                    .collect::<Vec<_>>()
+                    .join("|"),
+            ),
+            Pattern::Expr(_) | Pattern::Matcher(_) | Pattern::Rest(_) => None,
+        }
+    }
+
+    /// Generates the `#[doc]` attribute describing the captured keys with `doc`.
+    fn doc(&self, invert: bool) -> proc_macro2::TokenStream {
+        let matching = if invert { "not matching" } else { "matching" };
+        let doc = match self {
+            Pattern::Expr(expr) => format!("Captures keys {matching} `{}`", expr.to_token_stream()),
+            Pattern::Matcher(path) => {
+                let accepted = if invert { "rejected" } else { "accepted" };
+                format!("Captures keys {accepted} by `{}`", path.to_token_stream())
+            }
+            Pattern::Rest(_) => {
+                "Captures keys not matching any other `flat_regex` field".to_string()
+            }
+            _ => format!(
+                "Captures keys {matching} `{}`",
+                self.listed().unwrap_or_default()
+            ),
+        };
+        quote!(#[doc = #doc])
+    }
+}
+
+/// The arguments of a field attribute which darling can't parse, see [`FlatArg`].
+struct ExprArgs {
+    regex: Option<Expr>,
+    literals: Option<syn::ExprArray>,
+    key_access: Option<syn::ExprArray>,
+    routes: Option<syn::ExprArray>,
+}
+
+impl ExprArgs {
+    fn new(expr_args: Vec<FlatArg>) -> Self {
+        let mut args = ExprArgs {
+            regex: None,
+            literals: None,
+            key_access: None,
+            routes: None,
+        };
+        for arg in expr_args {
+            match (arg.name.to_string().as_str(), arg.value) {
+                ("regex", value) => args.regex = value,
+                ("literals", Some(Expr::Array(array))) => args.literals = Some(array),
+                ("key_access", Some(Expr::Array(array))) => args.key_access = Some(array),
+                ("routes", Some(Expr::Array(array))) => args.routes = Some(array),
+                (_, _) => abort!(arg.name, "expected a literal value for `{}`", arg.name),
+            }
+        }
+        args
+    }
+}
+
+/// Parses the string literals of an array argument like `["a", "b"]`.
+fn lit_strs(array: &syn::ExprArray, arg: &str) -> Vec<syn::LitStr> {
+    array
+        .elems
+        .iter()
+        .map(|e| match e {
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) => lit.clone(),
+            _ => abort!(e, "`{}` must only contain string literals", arg),
+        })
+        .collect()
+}
+
+/// How the `&str` for matching is obtained from a key.
+enum KeyAccess {
+    /// `key.as_ref()`
+    AsRef,
+    /// `key_access = "fn_name"`
+    Fn(syn::ExprPath),
+    /// `key_access = ["fn_a", "fn_b"]`, the first function returning `Ok` is used
+    Fallbacks(Vec<syn::ExprPath>),
+    /// `key_access_owned = "fn_name"`, called with a clone of the key and returning a `String`
+    Owned(syn::ExprPath),
+    /// `lossy_utf8`, byte string keys are converted with `String::from_utf8_lossy`
+    Lossy,
+}
+
+impl KeyAccess {
+    fn new(flat_field: &FlatRegex, expr_args: &ExprArgs) -> Self {
+        if flat_field.lossy_utf8.is_present() {
+            if flat_field.key_access.is_some()
+                || expr_args.key_access.is_some()
+                || flat_field.key_access_owned.is_some()
+            {
+                abort!(
+                    flat_field.ty,
+                    "`lossy_utf8` can't be combined with `key_access` or `key_access_owned`"
+                );
+            }
+            return KeyAccess::Lossy;
+        }
+        if let Some(fun) = &flat_field.key_access_owned {
+            if flat_field.key_access.is_some() || expr_args.key_access.is_some() {
+                abort!(
+                    fun,
+                    "`key_access_owned` can't be combined with `key_access`"
+                );
+            }
+            return KeyAccess::Owned(fun.clone());
+        }
+        match (&flat_field.key_access, &expr_args.key_access) {
+            (None, None) => KeyAccess::AsRef,
+            (Some(fun), None) => KeyAccess::Fn(fun.clone()),
+            (None, Some(array)) if array.elems.is_empty() => {
+                abort!(array, "`key_access` needs at least one function")
+            }
+            (None, Some(array)) => KeyAccess::Fallbacks(
+                lit_strs(array, "key_access")
+                    .iter()
+                    .map(|lit| match lit.parse() {
+                        Ok(path) => path,
+                        Err(e) => abort!(lit, e),
+                    })
+                    .collect(),
+            ),
+            (Some(fun), Some(_)) => abort!(fun, "`key_access` is set more than once"),
+        }
+    }
+
+    /// Generates the statement binding `key_str`.
+    ///
+    /// If all fallbacks fail `on_fail` is executed, which has to diverge.
+    fn bind_key_str(
+        &self,
+        key: &proc_macro2::TokenStream,
+        error: &proc_macro2::TokenStream,
+        on_fail: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        match self {
+            KeyAccess::AsRef => quote!(let key_str: &str = #key.as_ref();),
+            KeyAccess::Fn(fun) => quote!(let key_str = #fun(#key).map_err(#error::custom)?;),
+            KeyAccess::Owned(fun) => quote!(
+                let key_string: std::string::String = #fun(std::clone::Clone::clone(#key));
+                let key_str: &str = &key_string;
+            ),
+            KeyAccess::Lossy => quote!(
+                trait LossyKey {
+                    fn lossy_key(&self) -> std::borrow::Cow<'_, str>;
+                }
+
+                impl LossyKey for std::ffi::CString {
+                    fn lossy_key(&self) -> std::borrow::Cow<'_, str> {
+                        std::string::String::from_utf8_lossy(self.as_bytes())
+                    }
+                }
+
+                impl LossyKey for std::vec::Vec<u8> {
+                    fn lossy_key(&self) -> std::borrow::Cow<'_, str> {
+                        std::string::String::from_utf8_lossy(self)
+                    }
+                }
+
+                impl LossyKey for std::boxed::Box<[u8]> {
+                    fn lossy_key(&self) -> std::borrow::Cow<'_, str> {
+                        std::string::String::from_utf8_lossy(self)
+                    }
+                }
+
+                impl LossyKey for &[u8] {
+                    fn lossy_key(&self) -> std::borrow::Cow<'_, str> {
+                        std::string::String::from_utf8_lossy(self)
+                    }
+                }
+
+                let key_lossy = LossyKey::lossy_key(#key);
+                let key_str: &str = &key_lossy;
+            ),
+            KeyAccess::Fallbacks(funs) => {
+                let first = &funs[0];
+                let rest = &funs[1..];
+                quote!(
+                    let key_str: &str = match #first(#key).ok()#(.or_else(|| #rest(#key).ok()))* {
+                        std::option::Option::Some(key_str) => key_str,
+                        std::option::Option::None => #on_fail,
+                    };
+                )
+            }
+        }
+    }
+}
+
+impl Pattern {
+    fn new(
+        field: &Field,
+        flat_field: &FlatRegex,
+        expr_args: &ExprArgs,
+        prefix: &str,
+        field_path: &str,
+        base: Option<&syn::LitStr>,
+    ) -> Self {
+        if flat_field.rest.is_present() {
+            if flat_field.regex.is_some()
+                || expr_args.regex.is_some()
+                || expr_args.literals.is_some()
+                || flat_field.matcher.is_some()
+            {
+                abort!(
+                    field,
+                    "`rest` takes the keys not matching the other fields and has no pattern"
+                );
+            }
+            if flat_field.array.is_present() {
+                abort!(field, "`rest` is not supported for `array`");
+            }
+            return Pattern::Rest(prefix.to_string());
+        }
+        if let Some(matcher) = &flat_field.matcher {
+            if flat_field.regex.is_some()
+                || expr_args.regex.is_some()
+                || expr_args.literals.is_some()
+            {
+                abort!(
+                    matcher,
+                    "only one of `regex`, `literals` and `matcher` can be set"
+                );
+            }
+            if base.is_some() {
+                abort!(
+                    matcher,
+                    "`base` only applies to string literal `regex` patterns"
+                );
+            }
+            return Pattern::Matcher(matcher.clone());
+        }
+        if flat_field.word_boundary.is_present()
+            && (flat_field.glob.is_some()
+                || flat_field.prefix.is_some()
+                || flat_field.suffix.is_some()
+                || flat_field.replace.is_some())
+        {
+            abort!(
+                field,
+                "`word_boundary` can't be combined with `glob`, `prefix`, `suffix` or `replace`"
+            );
+        }
+        if let Some(glob) = &flat_field.glob {
+            return Pattern::glob(glob, flat_field, expr_args, field_path, base);
+        }
+        match (
+            &flat_field.regex,
+            expr_args.regex.clone(),
+            expr_args.literals.clone(),
+        ) {
+            (Some(lit), None, None)
+                if flat_field.prefix.is_some() || flat_field.suffix.is_some() =>
+            {
+                Pattern::affixed(field, flat_field, lit, field_path, base)
+            }
+            (Some(lit), None, None) => {
+                let lit = match base {
+                    Some(base) => syn::LitStr::new(&(base.value() + &lit.value()), lit.span()),
+                    None => lit.clone(),
+                };
+                if let Err(e) = regex::Regex::new(&lit.value()) {
+                    abort!(lit, "invalid regex in {}: {}", field_path, e);
+                }
+                let lit = ascii_case_insensitive(flat_field, &lit, field_path);
+                Pattern::Lit(word_boundary(flat_field, &lit, field_path))
+            }
+            _ if flat_field.ascii_case_insensitive.is_present() => {
+                abort!(
+                    field,
+                    "`ascii_case_insensitive` needs a string literal `regex`"
+                )
+            }
+            _ if flat_field.ci_literals.is_present() => {
+                abort!(field, "`ci_literals` needs a string literal `regex`")
+            }
+            _ if flat_field.word_boundary.is_present() => {
+                abort!(field, "`word_boundary` needs a string literal `regex`")
+            }
+            _ if flat_field.prefix.is_some() || flat_field.suffix.is_some() => {
+                abort!(field, "`prefix` and `suffix` need a string literal `regex`")
+            }
+            (None, Some(_), None) | (None, None, Some(_)) if base.is_some() => {
+                abort!(
+                    field,
+                    "`base` only applies to string literal `regex` patterns"
+                )
+            }
+            (None, Some(expr), None) => Pattern::Expr(Box::new(expr)),
+            (None, None, Some(array)) => Pattern::literals(&array),
+            (None, None, None) => abort!(
+                field,
+                "missing `regex`, `glob` or `matcher` in flat_regex attribute"
+            ),
+            _ => abort!(field, "only one of `regex` and `literals` can be set"),
+        }
+    }
+
+    /// The pattern of a `glob`, translated to a regex.
+    fn glob(
+        glob: &syn::LitStr,
+        flat_field: &FlatRegex,
+        expr_args: &ExprArgs,
+        field_path: &str,
+        base: Option<&syn::LitStr>,
+    ) -> Self {
+        if flat_field.regex.is_some()
+            || expr_args.regex.is_some()
+            || expr_args.literals.is_some()
+            || flat_field.prefix.is_some()
+            || flat_field.suffix.is_some()
+            || base.is_some()
+        {
+            abort!(
+                glob,
+                "`glob` can't be combined with `regex`, `literals`, `prefix`, `suffix` or `base`"
+            );
+        }
+        let regex = match glob_to_regex(&glob.value()) {
+            Ok(regex) => regex,
+            Err(e) => abort!(glob, "invalid glob in {}: {}", field_path, e),
+        };
+        if let Err(e) = regex::Regex::new(&regex) {
+            abort!(glob, "invalid glob in {}: {}", field_path, e);
+        }
+        Pattern::Lit(ascii_case_insensitive(
+            flat_field,
+            &syn::LitStr::new(&regex, glob.span()),
+            field_path,
+        ))
+    }
+
+    /// The pattern of a `regex` matching the rest of the key between the literal `prefix` and `suffix`.
+    fn affixed(
+        field: &Field,
+        flat_field: &FlatRegex,
+        lit: &syn::LitStr,
+        field_path: &str,
+        base: Option<&syn::LitStr>,
+    ) -> Self {
+        if base.is_some() {
+            abort!(field, "`prefix` and `suffix` can't be combined with `base`");
+        }
+        if flat_field.array.is_present()
+            || flat_field.into_indexed_vec.is_present()
+            || flat_field.captures.is_present()
+            || flat_field.replace.is_some()
+        {
+            abort!(
+                field,
+                "`prefix` and `suffix` can't be combined with `array`, `into_indexed_vec`, `captures` or `replace`"
+            );
+        }
+        if let Err(e) = regex::Regex::new(&lit.value()) {
+            abort!(lit, "invalid regex in {}: {}", field_path, e);
+        }
+        let lit = ascii_case_insensitive(flat_field, lit, field_path);
+        // e.g. a `(?x)` comment without a trailing newline comments out the closing group
+        if let Err(e) = regex::Regex::new(&format!("^(?:{})$", lit.value())) {
+            abort!(
+                lit,
+                "the regex of {} can't be anchored between `prefix` and `suffix`: {}",
+                field_path,
+                e
+            );
+        }
+        Pattern::Affixed {
+            prefix: flat_field
+                .prefix
+                .as_ref()
+                .map(syn::LitStr::value)
+                .unwrap_or_default(),
+            middle: lit,
+            suffix: flat_field
+                .suffix
+                .as_ref()
+                .map(syn::LitStr::value)
+                .unwrap_or_default(),
+        }
+    }
+
+    #[cfg(feature = "aho-corasick")]
+    fn literals(array: &syn::ExprArray) -> Self {
+        let lits = lit_strs(array, "literals");
+        if let Err(e) = aho_corasick::AhoCorasick::new(lits.iter().map(syn::LitStr::value)) {
+            abort!(array, e.to_string());
+        }
+        Pattern::Literals(lits)
+    }
+
+    #[cfg(not(feature = "aho-corasick"))]
+    fn literals(array: &syn::ExprArray) -> Self {
+        abort!(array, "`literals` needs the `aho-corasick` feature")
+    }
+
+    /// Generates the function `fun` returning the matcher, which is built once on the first call.
+    /// It's nested in the helper of the field, or shared by its serialize and deserialize helpers.
+    ///
+    /// A literal pattern is validated at compile time, an expression pattern is only known at runtime
+    /// and its error is returned.
+    fn cached_matcher(
+        &self,
+        fun: &Ident,
+        limits: &RegexLimits,
+        krate: &syn::Path,
+    ) -> proc_macro2::TokenStream {
+        let (ty, error, build) = match self {
+            Pattern::Lit(lit) => {
+                limits.validate(&lit.value(), lit);
+                (
+                    quote!(#krate::__regex::Regex),
+                    quote!(#krate::__regex::Error),
+                    limits.build(
+                        &quote!(#krate::__regex::Regex),
+                        &quote!(#krate::__regex::RegexBuilder),
+                        &quote!(#lit),
+                    ),
+                )
+            }
+            Pattern::Expr(expr) => (
+                quote!(#krate::__regex::Regex),
+                quote!(#krate::__regex::Error),
+                limits.build(
+                    &quote!(#krate::__regex::Regex),
+                    &quote!(#krate::__regex::RegexBuilder),
+                    &quote!(#expr),
+                ),
+            ),
+            Pattern::Affixed { middle, .. } => {
+                // the stripped rest of the key has to match as a whole
+                let anchored = format!("^(?:{})$", middle.value());
+                limits.validate(&anchored, middle);
+                (
+                    quote!(#krate::__regex::Regex),
+                    quote!(#krate::__regex::Error),
+                    limits.build(
+                        &quote!(#krate::__regex::Regex),
+                        &quote!(#krate::__regex::RegexBuilder),
+                        &quote!(#anchored),
+                    ),
+                )
+            }
+            #[cfg(feature = "aho-corasick")]
+            Pattern::Literals(lits) => {
+                limits.reject(&lits[0], "`literals`");
+                (
+                    quote!(#krate::__aho_corasick::AhoCorasick),
+                    quote!(#krate::__aho_corasick::BuildError),
+                    quote!(#krate::__aho_corasick::AhoCorasick::new([#(#lits),*])),
+                )
+            }
+            // a function needs no building
+            Pattern::Matcher(matcher) => {
+                limits.reject(matcher, "`matcher`");
+                return quote!();
+            }
+            Pattern::Rest(prefix) => {
+                let patterns_fun = rest_patterns_fn(prefix);
+                (
+                    quote!(#krate::__regex::RegexSet),
+                    quote!(#krate::__regex::Error),
+                    limits.build(
+                        &quote!(#krate::__regex::RegexSet),
+                        &quote!(#krate::__regex::RegexSetBuilder),
+                        &quote!(#patterns_fun()),
+                    ),
+                )
+            }
+        };
+        quote!(
+            fn #fun() -> std::result::Result<&'static #ty, #error> {
+                static MATCHER: std::sync::OnceLock<#ty> = std::sync::OnceLock::new();
+                if let std::option::Option::Some(matcher) = MATCHER.get() {
+                    return std::result::Result::Ok(matcher);
+                }
+                let matcher = #build?;
+                std::result::Result::Ok(MATCHER.get_or_init(|| matcher))
+            }
+        )
+    }
+
+    /// Generates the expression getting the matcher from the function generated by [`Pattern::cached_matcher`],
+    /// errors are reported through `error`.
+    fn matcher_new(
+        &self,
+        fun: &Ident,
+        error: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        if let Pattern::Matcher(path) = self {
+            quote!(#path as fn(&str) -> bool)
+        } else {
+            quote!(#fun().map_err(#error::custom)?)
+        }
+    }
+
+    /// Generates the condition for taking the key `key_str`.
+    fn is_match(&self, key_str: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match self {
+            Pattern::Matcher(_) => quote!(re(#key_str)),
+            Pattern::Rest(prefix) => {
+                let siblings_fun = rest_siblings_fn(prefix);
+                quote!(!#siblings_fun(re, #key_str))
+            }
+            // the cheap literal checks run before the regex
+            Pattern::Affixed { prefix, suffix, .. } => quote!(
+                #key_str
+                    .strip_prefix(#prefix)
+                    .and_then(|key| key.strip_suffix(#suffix))
+                    .is_some_and(|middle| re.is_match(middle))
+            ),
+            _ => quote!(re.is_match(#key_str)),
+        }
+    }
+}
+
+/// Generates the function `fun` returning the DFA of `precompile`, which is built and serialized at compile time,
+/// so no regex is compiled at runtime.
+///
+/// The DFA is embedded in both byte orders, the target may not be the host building it.
+#[cfg(feature = "precompile")]
+fn precompiled_matcher(
+    pattern: &Pattern,
+    fun: &Ident,
+    field: &Field,
+    field_path: &str,
+    krate: &syn::Path,
+) -> proc_macro2::TokenStream {
+    use regex_automata::dfa::{dense, StartKind};
+
+    let (pattern, span) = match pattern {
+        Pattern::Lit(lit) => (lit.value(), lit.span()),
+        // the stripped rest of the key has to match as a whole
+        Pattern::Affixed { middle, .. } => (format!("^(?:{})$", middle.value()), middle.span()),
+        _ => abort!(
+            field,
+            "`precompile` needs a string literal `regex` or `glob`"
+        ),
+    };
+    // the DFA only runs unanchored searches, and without quit bytes they can't fail. A Unicode word boundary would
+    // need quit bytes and fails to build instead.
+    let config = dense::DFA::config()
+        .start_kind(StartKind::Unanchored)
+        .unicode_word_boundary(false);
+    let dfa = match dense::Builder::new().configure(config).build(&pattern) {
+        Ok(dfa) => dfa,
+        Err(e) => abort!(span, "can't precompile the regex of {}: {}", field_path, e),
+    };
+    let (le, pad) = dfa.to_bytes_little_endian();
+    let le = syn::LitByteStr::new(&le[pad..], span);
+    let (be, pad) = dfa.to_bytes_big_endian();
+    let be = syn::LitByteStr::new(&be[pad..], span);
+    quote!(
+        fn #fun() -> std::result::Result<&'static #krate::__PrecompiledDfa, #krate::__DfaDeserializeError> {
+            #[cfg(target_endian = "little")]
+            static BYTES: &#krate::__AlignedBytes<[u8]> = &#krate::__AlignedBytes { align: [], bytes: *#le };
+            #[cfg(target_endian = "big")]
+            static BYTES: &#krate::__AlignedBytes<[u8]> = &#krate::__AlignedBytes { align: [], bytes: *#be };
+            static MATCHER: std::sync::OnceLock<#krate::__PrecompiledDfa> = std::sync::OnceLock::new();
+            if let std::option::Option::Some(matcher) = MATCHER.get() {
+                return std::result::Result::Ok(matcher);
+            }
+            let matcher = #krate::__PrecompiledDfa::from_bytes(BYTES)?;
+            std::result::Result::Ok(MATCHER.get_or_init(|| matcher))
+        }
+    )
+}
+
+#[cfg(not(feature = "precompile"))]
+fn precompiled_matcher(
+    _pattern: &Pattern,
+    _fun: &Ident,
+    field: &Field,
+    _field_path: &str,
+    _krate: &syn::Path,
+) -> proc_macro2::TokenStream {
+    abort!(field, "`precompile` needs the `precompile` feature")
+}
+
+/// The `size_limit` and `dfa_size_limit` of a field, passed to the `RegexBuilder` of its matcher.
+struct RegexLimits {
+    size_limit: Option<usize>,
+    dfa_size_limit: Option<usize>,
+}
+
+impl RegexLimits {
+    fn new(flat_field: &FlatRegex) -> Self {
+        RegexLimits {
+            size_limit: flat_field.size_limit,
+            dfa_size_limit: flat_field.dfa_size_limit,
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        self.size_limit.is_none() && self.dfa_size_limit.is_none()
+    }
+
+    /// Generates building the matcher `ty` from `patterns`, with `builder` only if a limit is set.
+    fn build(
+        &self,
+        ty: &proc_macro2::TokenStream,
+        builder: &proc_macro2::TokenStream,
+        patterns: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        if self.is_default() {
+            return quote!(#ty::new(#patterns));
+        }
+        let size_limit = self.size_limit.map(|limit| quote!(.size_limit(#limit)));
+        let dfa_size_limit = self
+            .dfa_size_limit
+            .map(|limit| quote!(.dfa_size_limit(#limit)));
+        quote!(#builder::new(#patterns)#size_limit #dfa_size_limit.build())
+    }
+
+    /// Checks at compile time that `pattern` fits into the limits.
+    fn validate(&self, pattern: &str, span: impl ToTokens) {
+        if self.is_default() {
+            return;
+        }
+        let mut builder = regex::RegexBuilder::new(pattern);
+        if let Some(limit) = self.size_limit {
+            builder.size_limit(limit);
+        }
+        if let Some(limit) = self.dfa_size_limit {
+            builder.dfa_size_limit(limit);
+        }
+        if let Err(e) = builder.build() {
+            abort!(span, "the regex doesn't fit into the size limits: {}", e);
+        }
+    }
+
+    /// Aborts if a limit is set for a matcher which isn't a regex.
+    fn reject(&self, span: impl ToTokens, matcher: &str) {
+        if !self.is_default() {
+            abort!(
+                span,
+                "`size_limit` and `dfa_size_limit` can't be combined with {}",
+                matcher
+            );
+        }
+    }
+}
+
+/// Wraps `lit` in a `(?i-u:...)` group with `ascii_case_insensitive`, so only ASCII letters are case folded,
+/// or only its literal runs with `ci_literals`.
+///
+/// The flags are part of the pattern, which keeps `FLAT_REGEX_PATTERNS` and the siblings of `rest` fields in sync.
+fn ascii_case_insensitive(
+    flat_field: &FlatRegex,
+    lit: &syn::LitStr,
+    field_path: &str,
+) -> syn::LitStr {
+    if flat_field.ci_literals.is_present() {
+        if flat_field.ascii_case_insensitive.is_present() {
+            abort!(
+                lit,
+                "`ci_literals` can't be combined with `ascii_case_insensitive`, which already folds the whole regex"
+            );
+        }
+        return ci_literals(lit, field_path);
+    }
+    if !flat_field.ascii_case_insensitive.is_present() {
+        return lit.clone();
+    }
+    let folded = syn::LitStr::new(&format!("(?i-u:{})", lit.value()), lit.span());
+    if let Err(e) = regex::Regex::new(&folded.value()) {
+        abort!(
+            lit,
+            "the regex of {} can't be matched without Unicode for `ascii_case_insensitive`, \
+             e.g. `.` or negated classes would match single bytes: {}",
+            field_path,
+            e
+        );
+    }
+    folded
+}
+
+/// Wraps each run of ASCII literals with a letter in `lit` in a `(?i-u:...)` group for `ci_literals`,
+/// e.g. `^lanport_\d+$` becomes `^(?i-u:lanport_)\d+$`, leaving classes, `.` and non-ASCII literals exact.
+fn ci_literals(lit: &syn::LitStr, field_path: &str) -> syn::LitStr {
+    use regex_syntax::ast::{Ast, Literal};
+
+    fn push_run(run: &[&Literal], groups: &mut Vec<(usize, usize)>) {
+        if let (Some(first), Some(last)) = (run.first(), run.last()) {
+            if run.iter().any(|lit| lit.c.is_ascii_alphabetic()) {
+                groups.push((first.span.start.offset, last.span.end.offset));
+            }
+        }
+    }
+
+    fn literal_groups(ast: &Ast, groups: &mut Vec<(usize, usize)>) {
+        match ast {
+            Ast::Literal(lit) if lit.c.is_ascii() => push_run(&[lit], groups),
+            Ast::Concat(concat) => {
+                let mut run = Vec::new();
+                for ast in &concat.asts {
+                    match ast {
+                        Ast::Literal(lit) if lit.c.is_ascii() => run.push(&**lit),
+                        _ => {
+                            push_run(&run, groups);
+                            run.clear();
+                            literal_groups(ast, groups);
+                        }
+                    }
+                }
+                push_run(&run, groups);
+            }
+            Ast::Repetition(repetition) => literal_groups(&repetition.ast, groups),
+            Ast::Group(group) => literal_groups(&group.ast, groups),
+            Ast::Alternation(alternation) => {
+                for ast in &alternation.asts {
+                    literal_groups(ast, groups);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let pattern = lit.value();
+    let ast = match regex_syntax::ast::parse::Parser::new().parse(&pattern) {
+        Ok(ast) => ast,
+        Err(e) => abort!(lit, "invalid regex in {}: {}", field_path, e),
+    };
+    let mut groups = Vec::new();
+    literal_groups(&ast, &mut groups);
+    if groups.is_empty() {
+        abort!(
+            lit,
+            "the regex of {} has no ASCII letters outside of classes for `ci_literals`",
+            field_path
+        );
+    }
+    let mut folded = String::with_capacity(pattern.len() + groups.len() * 7);
+    let mut last = 0;
+    for (start, end) in groups {
+        folded.push_str(&pattern[last..start]);
+        folded.push_str("(?i-u:");
+        folded.push_str(&pattern[start..end]);
+        folded.push(')');
+        last = end;
+    }
+    folded.push_str(&pattern[last..]);
+    if let Err(e) = regex::Regex::new(&folded) {
+        abort!(
+            lit,
+            "the regex of {} can't be rewritten for `ci_literals`: {}",
+            field_path,
+            e
+        );
+    }
+    syn::LitStr::new(&folded, lit.span())
+}
+
+/// Loads the pattern of `regex_include = "path"` into `regex`, returning a const which includes the file,
+/// so the crate is rebuilt when the file changes.
+///
+/// Proc-macros can't get the path of the file they are called in on stable, so relative paths are resolved
+/// against `CARGO_MANIFEST_DIR` of the crate being compiled, like paths in `Cargo.toml`, not like `include_str!`.
+fn regex_include(
+    flat_field: &mut FlatRegex,
+    expr_args: &ExprArgs,
+) -> Option<proc_macro2::TokenStream> {
+    let include = flat_field.regex_include.as_ref()?;
+    if flat_field.regex.is_some()
+        || expr_args.regex.is_some()
+        || expr_args.literals.is_some()
+        || flat_field.glob.is_some()
+        || flat_field.matcher.is_some()
+    {
+        abort!(
+            include,
+            "`regex_include` can't be combined with `regex`, `literals`, `glob` or `matcher`"
+        );
+    }
+    let mut path = std::path::PathBuf::from(include.value());
+    if path.is_relative() {
+        match std::env::var_os("CARGO_MANIFEST_DIR") {
+            Some(dir) => path = std::path::Path::new(&dir).join(path),
+            None => abort!(
+                include,
+                "relative `regex_include` paths need `CARGO_MANIFEST_DIR`, use an absolute path"
+            ),
+        }
+    }
+    let pattern = match std::fs::read_to_string(&path) {
+        Ok(pattern) => pattern.trim().to_string(),
+        Err(e) => abort!(include, "can't read `{}`: {}", path.display(), e),
+    };
+    if pattern.is_empty() {
+        abort!(include, "`{}` contains no pattern", path.display());
+    }
+    flat_field.regex = Some(syn::LitStr::new(&pattern, include.span()));
+    let path = path.to_string_lossy();
+    Some(quote!(
+        const _: &str = std::include_str!(#path);
+    ))
+}
+
+/// Surrounds `lit` with word boundaries with `word_boundary`, so `port` matches `port_0` but not `export_0`.
+///
+/// Unlike `\b`, `_` and other non-alphanumeric characters separate words, as in dotted or underscored keys.
+/// The pattern is wrapped in its own group, which scopes its inline flags.
+fn word_boundary(flat_field: &FlatRegex, lit: &syn::LitStr, field_path: &str) -> syn::LitStr {
+    if !flat_field.word_boundary.is_present() {
+        return lit.clone();
+    }
+    let wrapped = syn::LitStr::new(
+        &format!(
+            r"(?:^|[^\p{{Alphabetic}}\p{{Nd}}])(?:{})(?:[^\p{{Alphabetic}}\p{{Nd}}]|$)",
+            lit.value()
+        ),
+        lit.span(),
+    );
+    // e.g. a `(?x)` comment without a trailing newline comments out the closing group
+    if let Err(e) = regex::Regex::new(&wrapped.value()) {
+        abort!(
+            lit,
+            "the regex of {} can't be wrapped in word boundaries: {}",
+            field_path,
+            e
+        );
+    }
+    wrapped
+}
+
+/// Translates a glob matching whole keys into an anchored regex.
+///
+/// `*` matches any characters, `?` a single character and `[...]` (or `[!...]`) a character class.
+fn glob_to_regex(glob: &str) -> Result<String, String> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                regex.push('[');
+                let mut class = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') if !class.is_empty() => break,
+                        Some(c) => class.push(c),
+                        None => return Err(format!("unclosed `[` in `{glob}`")),
+                    }
+                }
+                let class = match class.strip_prefix('!') {
+                    Some(negated) => {
+                        regex.push('^');
+                        negated
+                    }
+                    None => &class,
+                };
+                for c in class.chars() {
+                    // `-` keeps its meaning as range
+                    if c == '-' {
+                        regex.push(c);
+                    } else {
+                        regex.push_str(&regex::escape(&c.to_string()));
+                    }
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+/// `RouterStatus` as `router_status`.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 && !snake.ends_with('_') {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// A hash of `s` as 8 hex digits, the same in every build unlike `std`'s `DefaultHasher`.
+fn stable_hash(s: &str) -> String {
+    // 64 bit FNV-1a
+    let hash = s.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    // folded to the low 32 bits
+    format!("{:08x}", (hash ^ (hash >> 32)) & 0xffff_ffff)
+}
+
+/// The prefixes of `auto_prefix` for the fields of `owner`, the name of the struct or enum variant in snake case or
+/// lowercase followed by `_`.
+fn auto_prefixes(owner: &str) -> Vec<String> {
+    let name = owner.rsplit("::").next().unwrap_or(owner);
+    let mut prefixes = vec![
+        format!("{}_", snake_case(name)),
+        format!("{}_", name.to_lowercase()),
+    ];
+    prefixes.dedup();
+    prefixes
+}
+
+/// The name of the function testing if any other `flat_regex` field with `prefix` takes a key.
+fn rest_siblings_fn(prefix: &str) -> Ident {
+    Ident::new(
+        &format!("__flat_regex_siblings_{prefix}"),
+        Span::call_site(),
+    )
+}
+
+/// The name of the function listing the patterns of all `flat_regex` fields with `prefix`.
+fn rest_patterns_fn(prefix: &str) -> Ident {
+    Ident::new(
+        &format!("__flat_regex_patterns_{prefix}"),
+        Span::call_site(),
+    )
+}
+
+/// A `flat_regex` argument of the form `name` or `name = value`.
+///
+/// `syn::Meta` only allows literals as values, so arguments with an expression as value
+/// (e.g. `regex = concat!(..)`) are split off before the attribute is handed to darling.
+struct FlatArg {
+    name: Ident,
+    value: Option<Expr>,
+}
+
+impl Parse for FlatArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = syn::ext::IdentExt::parse_any(input)?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(FlatArg { name, value })
+    }
+}
+
+impl ToTokens for FlatArg {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let name = &self.name;
+        match &self.value {
+            Some(value) => tokens.extend(quote!(#name = #value)),
+            None => tokens.extend(quote!(#name)),
+        }
+    }
+}
+
+fn is_flat_regex(attr: &Attribute) -> bool {
+    attr.path.segments.last().unwrap().ident == "flat_regex"
+}
+
+/// Removes all expression valued arguments from the `flat_regex` attributes of `field`.
+fn split_expr_args(field: &Field) -> (Field, Vec<FlatArg>) {
+    let mut field = field.clone();
+    let mut expr_args = Vec::new();
+    for attr in field.attrs.iter_mut().filter(|a| is_flat_regex(a)) {
+        let args = match attr.parse_args_with(Punctuated::<FlatArg, Token![,]>::parse_terminated) {
+            Ok(args) => args,
+            Err(e) => abort!(e.span(), e),
+        };
+        let (lit_args, exprs): (Vec<_>, Vec<_>) = args
+            .into_iter()
+            .partition(|a| matches!(a.value, None | Some(Expr::Lit(_))));
+        expr_args.extend(exprs);
+        attr.tokens = quote!((#(#lit_args),*));
+    }
+    (field, expr_args)
+}
+
+/// Rewrites the `flat_regex` field `field` of the struct or enum variant `owner` (e.g. `Enum::Variant`),
+/// `prefix` makes the names of the generated helpers unique.
+fn replace_attr(
+    field: &Field,
+    prefix: &str,
+    owner: &str,
+    container: &SerdeContainer,
+    item_args: &ItemArgs,
+    generated: &mut Generated,
+) -> proc_macro2::TokenStream {
+    if !field.attrs.iter().any(is_flat_regex) {
+        return quote!(#field);
+    }
+    let (lit_field, expr_args) = split_expr_args(field);
+    let has_lit_args = lit_field
+        .attrs
+        .iter()
+        .filter(|a| is_flat_regex(a))
+        .any(|a| {
+            a.parse_args_with(Punctuated::<FlatArg, Token![,]>::parse_terminated)
+                .map_or(true, |args| !args.is_empty())
+        });
+    let mut flat_field = match FlatRegex::from_field(&lit_field) {
+        Ok(flat_field) => flat_field,
+        Err(e) => return e.write_errors(),
+    };
+    let expr_args = ExprArgs::new(expr_args);
+    check_serde_with(&field.attrs, container);
+    if let Some(include) = regex_include(&mut flat_field, &expr_args) {
+        generated.with_fn.push(include);
+    }
+    let field_name = flat_field
+        .ident
+        .as_ref()
+        .map_or_else(|| "0".to_string(), Ident::to_string);
+    let field_path = format!("{owner}::{field_name}");
+    if let Some(routes) = &expr_args.routes {
+        if has_lit_args
+            || expr_args.regex.is_some()
+            || expr_args.literals.is_some()
+            || expr_args.key_access.is_some()
+        {
+            abort!(
+                routes,
+                "`routes` can't be combined with other flat_regex arguments"
+            );
+        }
+        return routes_field(
+            field,
+            &flat_field,
+            routes,
+            prefix,
+            owner,
+            &field_path,
+            container,
+            item_args,
+            generated,
+        );
+    }
+    let pattern = Pattern::new(
+        field,
+        &flat_field,
+        &expr_args,
+        prefix,
+        &field_path,
+        item_args.base.as_ref(),
+    );
+    let key_access = KeyAccess::new(&flat_field, &expr_args);
+    let ctx = FieldCtx {
+        field,
+        flat_field: &flat_field,
+        container,
+        pattern: &pattern,
+        key_access: &key_access,
+        field_path: &field_path,
+        field_name: &field_name,
+        indexed: flat_field.array.is_present() || flat_field.into_indexed_vec.is_present(),
+    };
+    ctx.check_index_args();
+    ctx.check_value_from_capture();
+    ctx.check_key_from_capture();
+    ctx.rewrite(prefix, owner, item_args, generated)
+}
+
+/// A `flat_regex` field with its parsed arguments, shared by the functions generating its helpers.
+#[derive(Clone, Copy)]
+struct FieldCtx<'a> {
+    field: &'a Field,
+    flat_field: &'a FlatRegex,
+    container: &'a SerdeContainer,
+    pattern: &'a Pattern,
+    key_access: &'a KeyAccess,
+    /// e.g. `RouterStatus::lanport_status`
+    field_path: &'a str,
+    field_name: &'a str,
+    /// the entries are placed by the index captured from the key
+    indexed: bool,
+}
+
+impl<'a> FieldCtx<'a> {
+    /// Checks the arguments of `array`, `into_indexed_vec` and `contiguous`.
+    fn check_index_args(&self) {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            indexed,
+            ..
+        } = *self;
+        if flat_field.array.is_present() && flat_field.into_indexed_vec.is_present() {
+            abort!(
+                field,
+                "only one of `array` and `into_indexed_vec` can be set"
+            );
+        }
+        if let Some(fun) = flat_field.dedup_key.as_ref().filter(|_| indexed) {
+            abort!(
+                fun,
+                "`dedup_key` is not supported for `array` and `into_indexed_vec`"
+            );
+        }
+        if flat_field.max_index.is_some()
+            && !flat_field.into_indexed_vec.is_present()
+            && !flat_field.contiguous.is_present()
+        {
+            abort!(
+                field,
+                "`max_index` needs `into_indexed_vec` or `contiguous`"
+            );
+        }
+        if flat_field.contiguous.is_present() {
+            if flat_field.key_from_capture.is_none() {
+                abort!(
+                    field,
+                    "`contiguous` needs `key_from_capture` naming the index capture group"
+                );
+            }
+            if flat_field.array.is_present() {
+                abort!(
+                    field,
+                    "`array` always needs every index, `contiguous` only applies to maps and `into_indexed_vec`"
+                );
+            }
+            if let Pattern::Affixed { .. } = pattern {
+                abort!(
+                    field,
+                    "`contiguous` can't be combined with `prefix` and `suffix`"
+                );
+            }
+        }
+    }
+
+    /// Checks the arguments of `value_from_capture`.
+    fn check_value_from_capture(&self) {
+        let FieldCtx {
+            flat_field,
+            container,
+            pattern,
+            key_access,
+            indexed,
+            ..
+        } = *self;
+        let Some(capture) = &flat_field.value_from_capture else {
+            return;
+        };
+        if flat_field.key_from_capture.is_none() {
+            abort!(
+                capture,
+                "`value_from_capture` needs `key_from_capture` naming the capture group of the key"
+            );
+        }
+        if indexed
+            || !matches!(key_access, KeyAccess::AsRef)
+            || flat_field.borrow_key.is_present()
+            || flat_field.key_parse.is_present()
+            || flat_field.key_wrap.is_some()
+            || flat_field.replace.is_some()
+            || flat_field.auto_prefix.is_present()
+            || flat_field.urldecode.is_present()
+            || flat_field.invert.is_present()
+            || flat_field.captures.is_present()
+            || flat_field.value_variant.is_some()
+            || flat_field.value_into.is_present()
+            || flat_field.serialize_matching.is_present()
+            || container.serialize_only()
+        {
+            abort!(
+                capture,
+                "`value_from_capture` can't be combined with `array`, `into_indexed_vec`, `key_access`, `borrow_key`, \
+                 `key_parse`, `key_wrap`, `replace`, `auto_prefix`, `urldecode`, `invert`, `captures`, `value_variant`, \
+                 `value_into` or serializing only matching keys"
+            );
+        }
+        if let Pattern::Affixed { .. } = pattern {
+            abort!(
+                capture,
+                "`value_from_capture` can't be combined with `prefix` and `suffix`"
+            );
+        }
+        if let Pattern::Lit(lit) = pattern {
+            let re = regex::Regex::new(&lit.value()).unwrap();
+            if !re.capture_names().any(|n| n == Some(&capture.value())) {
+                abort!(
+                    capture,
+                    "the regex has no capture group named `{}`",
+                    capture.value()
+                );
+            }
+        }
+    }
+
+    /// Checks the arguments of `key_from_capture`, which `array` and `into_indexed_vec` need.
+    fn check_key_from_capture(&self) {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            indexed,
+            ..
+        } = *self;
+        if let Some(capture) = &flat_field.key_from_capture {
+            if !indexed
+                && !flat_field.contiguous.is_present()
+                && flat_field.value_from_capture.is_none()
+            {
+                abort!(
+                    capture,
+                    "`key_from_capture` is only supported together with `array`, `into_indexed_vec`, `contiguous` \
+                     or `value_from_capture`"
+                );
+            }
+            #[cfg(feature = "aho-corasick")]
+            if let Pattern::Literals(_) = pattern {
+                abort!(capture, "`key_from_capture` needs a `regex`");
+            }
+            if let Pattern::Matcher(_) | Pattern::Rest(_) = pattern {
+                abort!(capture, "`key_from_capture` needs a `regex`");
+            }
+            if let Pattern::Lit(lit) = pattern {
+                let re = regex::Regex::new(&lit.value()).unwrap();
+                if !re.capture_names().any(|n| n == Some(&capture.value())) {
+                    abort!(
+                        capture,
+                        "the regex has no capture group named `{}`",
+                        capture.value()
+                    );
+                }
+            }
+        } else if indexed {
+            abort!(
+                field,
+                "`array` and `into_indexed_vec` need `key_from_capture` naming the index capture group"
+            );
+        }
+    }
+
+    /// Generates the helpers of the field and returns the field with its serde attributes.
+    fn rewrite(
+        &self,
+        prefix: &str,
+        owner: &str,
+        item_args: &ItemArgs,
+        generated: &mut Generated,
+    ) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            container,
+            pattern,
+            ..
+        } = *self;
+        if flat_field.ident.is_none() && !container.transparent {
+            abort!(
+                field,
+                "flat_regex on tuple struct fields needs `#[serde(transparent)]`"
+            );
+        }
+        // the single field of a transparent newtype is named after its index, raw identifiers lose their `r#`
+        let ident = flat_field.ident.as_ref().map_or_else(
+            || Ident::new("_0", Span::call_site()),
+            syn::ext::IdentExt::unraw,
+        );
+        let bind_key = self.de_key_access(owner);
+        // the helpers are named after the field and a hash of its pattern, independent of the field order
+        let pattern_tokens = if let Pattern::Matcher(matcher) = pattern {
+            quote!(#matcher)
+        } else {
+            quote!(#pattern)
+        };
+        let name_hash = stable_hash(&format!("{owner}::{ident}={pattern_tokens}"));
+        let matcher_fn = Ident::new(
+            &format!("__flat_regex_matcher_{prefix}_{ident}_{name_hash}"),
+            Span::call_site(),
+        );
+        // the helpers of this field, which are only generated with `cfg_feature` enabled
+        let first_fn = generated.with_fn.len();
+        // serde calls `deserialize_with` and `serialize_with` by a path resolved next to the item, so the helpers
+        // can't be hidden in an anonymous `const _: () = { ... };` block. Associated functions of the item would
+        // clash with its generics. The matcher is nested in its only user and is only a free function if it's
+        // shared by the serialize and deserialize helpers.
+        let shared_matcher =
+            flat_field.serialize_matching.is_present() && !container.serialize_only();
+        let limits = RegexLimits::new(flat_field);
+        let matcher = self.matcher(&matcher_fn, &limits, &item_args.krate());
+        let nested_matcher = if shared_matcher {
+            generated.with_fn.push(matcher);
+            quote!()
+        } else {
+            matcher
+        };
+        self.register_pattern(prefix, owner, generated);
+        self.check_key_args();
+        self.check_value_args();
+        let skip_errors = self.skip_errors();
+        self.check_captures_args();
+        let (coll_ty, wrap, optional) = self.collection_type();
+        let error_on_collision = self.error_on_collision(coll_ty);
+        check_reserve_hint(flat_field, coll_ty);
+        let types = EntryTypes::new(self, coll_ty);
+        let mut serde_attr = self.serde_attr(&types);
+        if flat_field.serialize_matching.is_present() || container.serialize_only() {
+            let ser_fun_name = format!("__with_regex_ser_{prefix}_{ident}_{name_hash}");
+            generated.with_fn.push(self.serialize_helper(
+                &ser_fun_name,
+                &types,
+                &matcher_fn,
+                &nested_matcher,
+            ));
+            serde_attr.push(quote!(serialize_with = #ser_fun_name));
+        }
+        if flat_field.into_flat.is_present() {
+            generated.methods.push(self.flat_method(&types));
+        }
+        if flat_field.export_pattern.is_present() {
+            generated.methods.push(self.export_pattern_const());
+        }
+        if !container.serialize_only() {
+            let fun_name = format!("__with_regex_{prefix}_{ident}_{name_hash}");
+            let helper = DeHelper {
+                ctx: *self,
+                types: &types,
+                fun: Ident::new(&fun_name, Span::call_site()),
+                value_matcher_fn: Ident::new(&format!("{matcher_fn}_value"), Span::call_site()),
+                prefix,
+                limits: &limits,
+                bind_key,
+                nested_matcher,
+                matcher_fn: &matcher_fn,
+                regex_new: pattern.matcher_new(&matcher_fn, &quote!(A::Error)),
+                skip_errors,
+                error_on_collision,
+                optional,
+                wrap,
+                item_args,
+            };
+            generated.with_fn.push(helper.generate());
+            serde_attr.push(quote!(deserialize_with = #fun_name));
+            if let Some(merge) = helper.merge() {
+                generated
+                    .merges
+                    .push((flat_field.cfg_feature.clone(), merge));
+            }
+        }
+        if let Some(feature) = &flat_field.cfg_feature {
+            for with_fn in &mut generated.with_fn[first_fn..] {
+                *with_fn = quote!(#[cfg(feature = #feature)] #with_fn);
+            }
+        }
+        self.field_tokens(&serde_attr)
+    }
+
+    /// The field with its serde attributes `serde_attr`, which are only applied with `cfg_feature` enabled.
+    fn field_tokens(&self, serde_attr: &[proc_macro2::TokenStream]) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            ..
+        } = *self;
+        let vis = &flat_field.vis;
+        let ty = &flat_field.ty;
+        let attr = field
+            .attrs
+            .iter()
+            .filter(|a| !is_flat_regex(a))
+            .map(|a| quote!(#a));
+        let doc = flat_field
+            .doc
+            .is_present()
+            .then(|| pattern.doc(flat_field.invert.is_present()));
+        let serde_attr = if let Some(feature) = &flat_field.cfg_feature {
+            quote!(#[cfg_attr(feature = #feature, serde(#(#serde_attr),*))])
+        } else {
+            quote!(#[serde(#(#serde_attr),*)])
+        };
+        let member = flat_field.ident.as_ref().map(|ident| quote!(#ident:));
+        quote!(
+            #doc
+            #serde_attr
+            #(#attr)*
+            #vis #member #ty
+        )
+    }
+
+    /// Generates the statements binding `key_str` to the key as it's matched, with `auto_prefix` stripped and
+    /// with `urldecode` percent-decoded.
+    fn de_key_access(&self, owner: &str) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            container,
+            key_access,
+            indexed,
+            ..
+        } = *self;
+        let de_key_access = if flat_field.auto_prefix.is_present() {
+            if !matches!(key_access, KeyAccess::AsRef)
+                || indexed
+                || flat_field.borrow_key.is_present()
+                || flat_field.key_parse.is_present()
+                || flat_field.replace.is_some()
+                || flat_field.invert.is_present()
+                || flat_field.serialize_matching.is_present()
+                || container.serialize_only()
+            {
+                abort!(
+                    field,
+                    "`auto_prefix` can't be combined with `key_access`, `array`, `into_indexed_vec`, `borrow_key`, \
+                     `key_parse`, `replace`, `invert` or serializing only matching keys"
+                );
+            }
+            let prefixes = auto_prefixes(owner);
+            quote!(
+                let key_str: &str = match [#(#prefixes),*].iter().find_map(|prefix| key.strip_prefix(prefix)) {
+                    std::option::Option::Some(key_str) => key_str,
+                    std::option::Option::None => {
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                        continue;
+                    }
+                };
+            )
+        } else {
+            key_access.bind_key_str(
+                &quote!(&key),
+                &quote!(A::Error),
+                &quote!({
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                    continue;
+                }),
+            )
+        };
+        // with `urldecode` the key is matched percent-decoded, e.g. `port%5B0%5D` as `port[0]`
+        if flat_field.urldecode.is_present() {
+            if !cfg!(feature = "urldecode") {
+                abort!(field, "`urldecode` needs the `urldecode` feature");
+            }
+            if indexed
+                || flat_field.borrow_key.is_present()
+                || flat_field.key_parse.is_present()
+                || flat_field.replace.is_some()
+                || flat_field.serialize_matching.is_present()
+                || container.serialize_only()
+            {
+                abort!(
+                    field,
+                    "`urldecode` can't be combined with `array`, `into_indexed_vec`, `borrow_key`, `key_parse`, \
+                     `replace` or serializing only matching keys"
+                );
+            }
+            quote!(
+                #de_key_access
+                let decoded_key = percent_encoding::percent_decode_str(key_str).decode_utf8_lossy();
+                let key_str: &str = &decoded_key;
+            )
+        } else {
+            if flat_field.keep_encoded_key.is_present() {
+                abort!(field, "`keep_encoded_key` needs `urldecode`");
+            }
+            de_key_access
+        }
+    }
+
+    /// Generates the function `matcher_fn` returning the matcher of the field.
+    fn matcher(
+        &self,
+        matcher_fn: &Ident,
+        limits: &RegexLimits,
+        krate: &syn::Path,
+    ) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            indexed,
+            ..
+        } = *self;
+        if flat_field.precompile.is_present() {
+            if indexed
+                || flat_field.key_from_capture.is_some()
+                || flat_field.captures.is_present()
+                || flat_field.replace.is_some()
+                || !limits.is_default()
+            {
+                abort!(
+                    field,
+                    "`precompile` can't be combined with `array`, `into_indexed_vec`, `key_from_capture`, `captures`, \
+                     `replace`, `size_limit` or `dfa_size_limit`"
+                );
+            }
+            precompiled_matcher(pattern, matcher_fn, field, self.field_path, krate)
+        } else {
+            pattern.cached_matcher(matcher_fn, limits, krate)
+        }
+    }
+
+    /// Generates the condition for taking the key `key_str`, with `unmatched` the field takes the keys its
+    /// pattern doesn't match.
+    fn is_match(&self, key_str: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let is_match = self.pattern.is_match(key_str);
+        if self.flat_field.unmatched.is_present() {
+            quote!(!(#is_match))
+        } else {
+            is_match
+        }
+    }
+
+    /// Lists the pattern of the field in `FLAT_REGEX_PATTERNS` and for the `rest` fields with `prefix`.
+    fn register_pattern(&self, prefix: &str, owner: &str, generated: &mut Generated) {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            field_name,
+            indexed,
+            ..
+        } = *self;
+        // the keys of an `unmatched` field aren't described by its pattern, so it's listed as `None`
+        if flat_field.unmatched.is_present() {
+            if indexed
+                || flat_field.key_from_capture.is_some()
+                || flat_field.captures.is_present()
+                || flat_field.replace.is_some()
+                || flat_field.only.is_present()
+                || matches!(pattern, Pattern::Rest(_))
+            {
+                abort!(
+                    field,
+                    "`unmatched` can't be combined with `rest`, `array`, `into_indexed_vec`, `key_from_capture`, \
+                     `captures`, `replace` or `only`"
+                );
+            }
+            generated
+                .patterns
+                .push(quote!((#field_name, std::option::Option::None)));
+            return;
+        }
+        // `only` fails on keys no sibling takes, like `rest` it matches the original key against them
+        if flat_field.only.is_present() {
+            if flat_field.auto_prefix.is_present() || flat_field.urldecode.is_present() {
+                abort!(
+                    field,
+                    "`only` can't be combined with `auto_prefix` or `urldecode`"
+                );
+            }
+            generated.rest.push(prefix.to_string());
+        }
+        if let Pattern::Rest(_) = pattern {
+            // the siblings match the original key
+            if flat_field.auto_prefix.is_present() || flat_field.urldecode.is_present() {
+                abort!(
+                    field,
+                    "`rest` can't be combined with `auto_prefix` or `urldecode`"
+                );
+            }
+            generated.rest.push(prefix.to_string());
+            generated
+                .patterns
+                .push(quote!((#field_name, std::option::Option::None)));
+        } else if let Pattern::Matcher(matcher) = pattern {
+            generated
+                .patterns
+                .push(quote!((#field_name, std::option::Option::None)));
+            generated.prefix_matchers.push((
+                prefix.to_string(),
+                KeyTransform::new(flat_field, owner),
+                matcher.clone(),
+            ));
+        } else {
+            let listed = if pattern.is_const() {
+                quote!(std::option::Option::Some(#pattern))
+            } else {
+                quote!(std::option::Option::None)
+            };
+            generated.patterns.push(quote!((#field_name, #listed)));
+            generated.prefix_patterns.push((
+                prefix.to_string(),
+                KeyTransform::new(flat_field, owner),
+                quote!(#pattern),
+            ));
+        }
+    }
+
+    /// Checks the arguments of `deny_leading_wildcard`, `replace` and `invert`.
+    fn check_key_args(&self) {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            indexed,
+            ..
+        } = *self;
+        if flat_field.deny_leading_wildcard.is_present() {
+            match pattern {
+                Pattern::Lit(lit) => check_leading_wildcard(lit),
+                Pattern::Affixed { prefix, middle, .. } if prefix.is_empty() => {
+                    check_leading_wildcard(middle);
+                }
+                Pattern::Affixed { .. } => {}
+                _ => abort!(
+                    field,
+                    "`deny_leading_wildcard` can only check string literal `regex` patterns"
+                ),
+            }
+        }
+        if let Some(template) = &flat_field.replace {
+            match pattern {
+                Pattern::Lit(lit) => check_template(lit, template),
+                Pattern::Expr(_) => {}
+                _ => abort!(template, "`replace` needs a `regex`"),
+            }
+            if indexed || flat_field.key_parse.is_present() {
+                abort!(
+                    template,
+                    "`replace` can't be combined with `array`, `into_indexed_vec` or `key_parse`"
+                );
+            }
+            if flat_field.serialize_matching.is_present() {
+                abort!(
+                    template,
+                    "`replace` can't be combined with `serialize_matching`, rewritten keys don't match"
+                );
+            }
+        }
+        if flat_field.invert.is_present() && (indexed || flat_field.serialize_matching.is_present())
+        {
+            abort!(
+                field,
+                "`invert` can't be combined with `array`, `into_indexed_vec` or `serialize_matching`"
+            );
+        }
+    }
+
+    /// Whether a key colliding with a stored one is an error, with `on_collision = "error"`, which looks the key up
+    /// in the collection `coll_ty`.
+    fn error_on_collision(&self, coll_ty: &Type) -> bool {
+        let FieldCtx {
+            field,
+            flat_field,
+            indexed,
+            ..
+        } = *self;
+        let error_on_collision = match &flat_field.on_collision {
+            Some(on_collision) if on_collision.value() == "error" => true,
+            Some(on_collision) if on_collision.value() == "overwrite" => false,
+            Some(on_collision) => abort!(
+                on_collision,
+                "`on_collision` must be \"error\" or \"overwrite\""
+            ),
+            None => false,
+        };
+        if error_on_collision && (indexed || flat_field.multimap.is_present()) {
+            abort!(
+                field,
+                "`on_collision` can't be combined with `array`, `into_indexed_vec` or `multimap`"
+            );
+        }
+        if let (true, Some(on_collision)) = (error_on_collision, &flat_field.on_collision) {
+            if is_entry_seq(coll_ty) {
+                abort!(
+                    on_collision,
+                    "`on_collision = \"error\"` needs a map with `contains_key`, e.g. `HashMap` or `BTreeMap`, \
+                     not a sequence of entries"
+                );
+            }
+        }
+        error_on_collision
+    }
+
+    /// Checks the arguments of `key_wrap`, `stop_key`, `take` and the conversions of the values.
+    fn check_value_args(&self) {
+        let FieldCtx {
+            field,
+            flat_field,
+            key_access,
+            indexed,
+            ..
+        } = *self;
+        if let Some(fun) = &flat_field.key_wrap {
+            if !matches!(key_access, KeyAccess::AsRef)
+                || indexed
+                || flat_field.borrow_key.is_present()
+                || flat_field.key_parse.is_present()
+                || flat_field.invert.is_present()
+            {
+                abort!(
+                    fun,
+                    "`key_wrap` can't be combined with `key_access`, `array`, `into_indexed_vec`, `borrow_key`, `key_parse` or `invert`"
+                );
+            }
+        }
+        if let Some(stop_key) = flat_field.stop_key.as_ref().filter(|_| indexed) {
+            abort!(
+                stop_key,
+                "`stop_key` is not supported for `array` and `into_indexed_vec`"
+            );
+        }
+        if flat_field.take.is_some() && indexed {
+            abort!(
+                field,
+                "`take` is not supported for `array` and `into_indexed_vec`"
+            );
+        }
+        if flat_field.value_into.is_present()
+            && (indexed || flat_field.value_variant.is_some() || flat_field.captures.is_present())
+        {
+            abort!(
+                field,
+                "`value_into` can't be combined with `array`, `into_indexed_vec`, `value_variant` or `captures`"
+            );
+        }
+        match (&flat_field.value_as, &flat_field.value_convert) {
+            (Some(_), Some(_)) => {
+                if indexed
+                    || flat_field.value_variant.is_some()
+                    || flat_field.captures.is_present()
+                    || flat_field.value_into.is_present()
+                    || flat_field.value_from_capture.is_some()
+                {
+                    abort!(
+                        field,
+                        "`value_as` can't be combined with `array`, `into_indexed_vec`, `value_variant`, `captures`, \
+                         `value_into` or `value_from_capture`"
+                    );
+                }
+            }
+            (Some(ty), None) => abort!(ty, "`value_as` needs a `value_convert` function"),
+            (None, Some(fun)) => abort!(fun, "`value_convert` needs the wire type in `value_as`"),
+            (None, None) => {}
+        }
+        if let Some(as_ty) = &flat_field.value_serde_as {
+            if !cfg!(feature = "serde_with") {
+                abort!(as_ty, "`value_serde_as` needs the `serde_with` feature");
+            }
+            if indexed
+                || flat_field.value_variant.is_some()
+                || flat_field.captures.is_present()
+                || flat_field.value_into.is_present()
+                || flat_field.value_as.is_some()
+                || flat_field.value_from_capture.is_some()
+            {
+                abort!(
+                    as_ty,
+                    "`value_serde_as` can't be combined with `array`, `into_indexed_vec`, `value_variant`, `captures`, \
+                     `value_into`, `value_as` or `value_from_capture`"
+                );
+            }
+        }
+    }
+
+    /// Whether values failing to deserialize are skipped, with `on_error = "skip"`.
+    ///
+    /// Also checks `value_flat_regex`, which can't skip errors.
+    fn skip_errors(&self) -> bool {
+        let FieldCtx {
+            field,
+            flat_field,
+            container,
+            field_path,
+            indexed,
+            ..
+        } = *self;
+        let skip_errors = match &flat_field.on_error {
+            Some(on_error) if on_error.value() == "skip" => true,
+            Some(on_error) if on_error.value() == "fail" => false,
+            Some(on_error) => abort!(on_error, "`on_error` must be \"skip\" or \"fail\""),
+            None => false,
+        };
+        if skip_errors {
+            if container.transparent {
+                abort!(
+                    field,
+                    "`on_error = \"skip\"` needs a flattened field, values of transparent newtypes aren't buffered"
+                );
+            }
+            if indexed || flat_field.value_variant.is_some() || flat_field.captures.is_present() {
+                abort!(
+                    field,
+                    "`on_error = \"skip\"` can't be combined with `array`, `into_indexed_vec`, `value_variant` or `captures`"
+                );
+            }
+        }
+        if let Some(value_regex) = &flat_field.value_pattern {
+            if indexed
+                || flat_field.value_variant.is_some()
+                || flat_field.captures.is_present()
+                || flat_field.value_into.is_present()
+                || flat_field.value_as.is_some()
+                || flat_field.value_serde_as.is_some()
+                || flat_field.value_from_capture.is_some()
+                || skip_errors
+            {
+                abort!(
+                    value_regex,
+                    "`value_flat_regex` can't be combined with `array`, `into_indexed_vec`, `value_variant`, `captures`, \
+                     `value_into`, `value_as`, `value_serde_as`, `value_from_capture` or `on_error = \"skip\"`"
+                );
+            }
+            if let Err(e) = regex::Regex::new(&value_regex.value()) {
+                abort!(
+                    value_regex,
+                    "invalid `value_flat_regex` in {}: {}",
+                    field_path,
+                    e
+                );
+            }
+        }
+        skip_errors
+    }
+
+    /// Checks the arguments of `captures` and `key_parse`.
+    fn check_captures_args(&self) {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            key_access,
+            indexed,
+            ..
+        } = *self;
+        if flat_field.captures.is_present() {
+            if !matches!(pattern, Pattern::Lit(_) | Pattern::Expr(_)) {
+                abort!(field, "`captures` needs a `regex`");
+            }
+            if indexed || flat_field.borrow_key.is_present() || flat_field.value_variant.is_some() {
+                abort!(
+                    field,
+                    "`captures` can't be combined with `array`, `borrow_key` or `value_variant`"
+                );
+            }
+        }
+        if flat_field.key_parse.is_present() {
+            if !matches!(key_access, KeyAccess::AsRef) {
+                abort!(
+                    field,
+                    "`key_parse` can't be combined with `key_access`, keys are matched as strings"
+                );
+            }
+            if indexed {
+                abort!(
+                    field,
+                    "`key_parse` is not supported for `array` and `into_indexed_vec`, keys are always strings"
+                );
+            }
+        }
+    }
+
+    /// The collection the entries are deserialized into, the conversion into the field type and whether the
+    /// field is an `Option`.
+    fn collection_type(&self) -> (&'a Type, Option<proc_macro2::TokenStream>, bool) {
+        let FieldCtx {
+            field,
+            flat_field,
+            container,
+            indexed,
+            ..
+        } = *self;
+        let ty = &flat_field.ty;
+        // with `wrap` the collection is the innermost type of the field, e.g. `Arc<HashMap<K, V>>`
+        let coll_ty = if flat_field.wrap.is_some() {
+            if indexed {
+                abort!(
+                    field,
+                    "`wrap` is not supported for `array` and `into_indexed_vec`"
+                );
+            }
+            wrapped_inner(ty)
+        } else {
+            ty
+        };
+        let wrap = flat_field.wrap.as_ref().map(|fun| quote!(.map(#fun)));
+        // with `collect_into` the entries are collected into the given type and converted into the field type,
+        // e.g. a newtype checking its invariants in `From` or `TryFrom`
+        let (coll_ty, wrap) = match (&flat_field.collect_into, &flat_field.try_collect_into) {
+            (None, None) => (coll_ty, wrap),
+            (Some(_), Some(_)) => abort!(
+                field,
+                "only one of `collect_into` and `try_collect_into` can be set"
+            ),
+            (Some(coll_ty), None) | (None, Some(coll_ty)) => {
+                if indexed
+                    || flat_field.wrap.is_some()
+                    || flat_field.serialize_matching.is_present()
+                    || flat_field.into_flat.is_present()
+                    || container.serialize_only()
+                {
+                    abort!(
+                        field,
+                        "`collect_into` and `try_collect_into` can't be combined with `array`, `into_indexed_vec`, \
+                         `wrap`, `into_flat` or serializing only matching keys"
+                    );
+                }
+                let wrap = if flat_field.collect_into.is_some() {
+                    quote!(.map(<#ty as std::convert::From<#coll_ty>>::from))
+                } else {
+                    quote!(.and_then(|collection| {
+                        <#ty as std::convert::TryFrom<#coll_ty>>::try_from(collection).map_err(D::Error::custom)
+                    }))
+                };
+                (coll_ty, Some(wrap))
+            }
+        };
+        // an `Option` field is `None` for `null`, with `empty_is_none` also without matching keys
+        let optional = flat_field.wrap.is_none()
+            && flat_field.collect_into.is_none()
+            && flat_field.try_collect_into.is_none()
+            && !indexed
+            && option_inner(ty).is_some();
+        let coll_ty = if optional {
+            if flat_field.serialize_matching.is_present()
+                || flat_field.into_flat.is_present()
+                || container.serialize_only()
+            {
+                abort!(
+                    field,
+                    "`Option` fields can't be combined with `into_flat` or serializing only matching keys"
+                );
+            }
+            option_inner(ty).unwrap()
+        } else {
+            if flat_field.empty_is_none.is_present() {
+                abort!(field, "`empty_is_none` needs an `Option` field");
+            }
+            coll_ty
+        };
+        (coll_ty, wrap, optional)
+    }
+
+    /// The serde attributes of the field besides its helpers.
+    fn serde_attr(&self, types: &EntryTypes) -> Vec<proc_macro2::TokenStream> {
+        let FieldCtx {
+            field,
+            flat_field,
+            container,
+            ..
+        } = *self;
+        // serde doesn't allow flatten on newtypes, a transparent newtype gets the whole map anyway
+        let mut serde_attr = if container.transparent {
+            vec![]
+        } else {
+            vec![quote!(flatten)]
+        };
+        // the captures are derived from the keys of another field
+        if flat_field.captures.is_present() {
+            serde_attr.push(quote!(skip_serializing));
+        }
+        // with `auto_borrow` borrowed keys or values get `#[serde(borrow)]`, unless the field already has it
+        if flat_field.auto_borrow.is_present()
+            && (types.key_life.is_some() || types.val_life.is_some())
+            && !has_serde_borrow(&field.attrs)
+        {
+            serde_attr.push(quote!(borrow));
+        }
+        serde_attr
+    }
+
+    /// Generates the serialize helper `fun_name`, which only serializes the matching keys.
+    fn serialize_helper(
+        &self,
+        fun_name: &str,
+        types: &EntryTypes,
+        matcher_fn: &Ident,
+        nested_matcher: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            key_access,
+            indexed,
+            ..
+        } = *self;
+        if indexed {
+            abort!(
+                field,
+                "serializing only matching keys is not supported for `array` and `into_indexed_vec`"
+            );
+        }
+        if let Some(fun) = &flat_field.wrap {
+            abort!(
+                fun,
+                "serializing only matching keys is not supported with `wrap`"
+            );
+        }
+        let ty = &flat_field.ty;
+        let ser_r = Ident::new(fun_name, Span::call_site());
+        let lifetimes = types.key_life.iter().chain(
+            types
+                .val_life
+                .iter()
+                .filter(|v| Some(*v) != types.key_life.as_ref()),
+        );
+        let ser_key_access = if types.key_parse {
+            quote!(
+                let key_string = std::string::ToString::to_string(key);
+                let key_str: &str = &key_string;
+            )
+        } else {
+            key_access.bind_key_str(&quote!(key), &quote!(S::Error), &quote!(continue))
+        };
+        let ser_regex_new = pattern.matcher_new(matcher_fn, &quote!(S::Error));
+        let ser_is_match = self.is_match(&quote!(key_str));
+        quote!(
+        #[inline]
+        fn #ser_r<#(#lifetimes,)* S>(
+            map: &#ty,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer, {
+            use serde::ser::{Error, SerializeMap};
+
+            #nested_matcher
+
+            let re = #ser_regex_new;
+            let mut ser_map = serializer.serialize_map(std::option::Option::None)?;
+            for (key, val) in map {
+                #ser_key_access
+                if #ser_is_match {
+                    ser_map.serialize_entry(key, val)?;
+                }
+            }
+            ser_map.end()
+        }
+        )
+    }
+
+    /// Generates the method `{field}_into_flat` of `into_flat`.
+    fn flat_method(&self, types: &EntryTypes) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            key_access,
+            indexed,
+            ..
+        } = *self;
+        let Some(field_ident) = &flat_field.ident else {
+            abort!(field, "`into_flat` needs a named field");
+        };
+        if !cfg!(feature = "json") {
+            abort!(field, "`into_flat` needs the `json` feature");
+        }
+        if indexed || flat_field.invert.is_present() || flat_field.captures.is_present() {
+            abort!(
+                field,
+                "`into_flat` can't be combined with `array`, `into_indexed_vec`, `invert` or `captures`"
+            );
+        }
+        if let Some(fun) = &flat_field.wrap {
+            abort!(fun, "`into_flat` is not supported with `wrap`");
+        }
+        let vis = &flat_field.vis;
+        let method = Ident::new(&format!("{field_ident}_into_flat"), Span::call_site());
+        let flat_key_access = match key_access {
+            KeyAccess::Fn(fun) => quote!(
+                let key_str: &str = match #fun(key) {
+                    std::result::Result::Ok(key_str) => key_str,
+                    std::result::Result::Err(_) => continue,
+                };
+            ),
+            _ if types.key_parse => quote!(
+                let key_string = std::string::ToString::to_string(key);
+                let key_str: &str = &key_string;
+            ),
+            _ => {
+                key_access.bind_key_str(&quote!(key), &quote!(serde_json::Error), &quote!(continue))
+            }
+        };
+        let doc = format!(
+            "The entries of `{field_ident}` as flat JSON keys, keys which can't be accessed as `&str` are left out.\n\n\
+             # Panics\n\nPanics if a value fails to serialize to JSON."
+        );
+        quote!(
+            #[doc = #doc]
+            #vis fn #method(&self) -> serde_json::Map<std::string::String, serde_json::Value> {
+                let mut flat = serde_json::Map::new();
+                for (key, val) in &self.#field_ident {
+                    #flat_key_access
+                    let val = serde_json::to_value(val)
+                        .expect(concat!("value of `", stringify!(#field_ident), "` failed to serialize"));
+                    flat.insert(key_str.to_string(), val);
+                }
+                flat
+            }
+        )
+    }
+
+    /// Generates the associated const of `export_pattern` named after the field, e.g. `PORTS_PATTERN`.
+    fn export_pattern_const(&self) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            field,
+            flat_field,
+            pattern,
+            ..
+        } = *self;
+        let Some(field_ident) = &flat_field.ident else {
+            abort!(field, "`export_pattern` needs a named field");
+        };
+        let value = match (pattern, pattern.listed()) {
+            (_, Some(listed)) => quote!(#listed),
+            (Pattern::Expr(expr), None) => quote!(#expr),
+            _ => abort!(
+                field,
+                "`export_pattern` needs a `regex`, `glob` or `literals` pattern"
+            ),
+        };
+        let name = Ident::new(
+            &format!(
+                "{}_PATTERN",
+                syn::ext::IdentExt::unraw(field_ident)
+                    .to_string()
+                    .to_uppercase()
+            ),
+            field_ident.span(),
+        );
+        let doc = format!("The pattern of the flat_regex field `{field_ident}`.");
+        quote!(
+            #[doc = #doc]
+            pub const #name: &'static str = #value;
+        )
+    }
+}
+
+/// The types of the entries of a `flat_regex` field.
+struct EntryTypes<'a> {
+    /// the collection the entries are deserialized into, e.g. the inner type of an `Option` field
+    coll_ty: &'a Type,
+    /// the type of the keys in the input, with `invert` the value of the entry
+    key: GenericArgument,
+    /// the type of the values in the input, with `invert` the key of the entry
+    value: GenericArgument,
+    /// the entry as it's stored from `key` and `val`
+    entry: proc_macro2::TokenStream,
+    /// the type of a single value in the input, with `multimap` the values are collected into a `Vec` per key
+    item: GenericArgument,
+    /// integer keys are matched by their decimal string and may be strings or integers in the input
+    int_key: bool,
+    key_parse: bool,
+    key_life: Option<Lifetime>,
+    val_life: Option<Lifetime>,
+}
+
+impl<'a> EntryTypes<'a> {
+    fn new(ctx: &FieldCtx, coll_ty: &'a Type) -> Self {
+        let FieldCtx {
+            field,
+            flat_field,
+            key_access,
+            indexed,
+            ..
+        } = *ctx;
+        let ty = &flat_field.ty;
+        // get inner generic values
+        let (key, value) = if flat_field.array.is_present() {
+            (parse_quote!(std::string::String), array_inner(ty).0)
+        } else if flat_field.into_indexed_vec.is_present() {
+            (parse_quote!(std::string::String), vec_inner(ty))
+        } else {
+            inner(coll_ty)
+        };
+        // the types of the entries in the collection, with `invert` the value of the input is the key of the entry
+        let (key, value, entry) = if flat_field.invert.is_present() {
+            (value, key, quote!((val, key)))
+        } else {
+            (key, value, quote!((key, val)))
+        };
+        let item = if flat_field.multimap.is_present() {
+            if indexed
+                || flat_field.invert.is_present()
+                || flat_field.captures.is_present()
+                || flat_field.wrap.is_some()
+            {
+                abort!(
+                    field,
+                    "`multimap` can't be combined with `array`, `into_indexed_vec`, `invert`, `captures` or `wrap`"
+                );
+            }
+            match &value {
+                GenericArgument::Type(Type::Path(path))
+                    if path.path.segments.last().unwrap().ident == "Vec" =>
+                {
+                    vec_inner(&Type::Path(path.clone()))
+                }
+                _ => abort!(value, "`multimap` needs a map with `Vec<V>` values"),
+            }
+        } else {
+            value.clone()
+        };
+        let int_key = matches!(key_access, KeyAccess::AsRef)
+            && !indexed
+            && !flat_field.borrow_key.is_present()
+            && !flat_field.key_parse.is_present()
+            && flat_field.key_wrap.is_none()
+            && flat_field.value_from_capture.is_none()
+            && is_integer(&key);
+        EntryTypes {
+            coll_ty,
+            key_life: lifetime(&key),
+            val_life: lifetime(&value),
+            key,
+            value,
+            entry,
+            item,
+            int_key,
+            key_parse: flat_field.key_parse.is_present() || int_key,
+        }
+    }
+
+    /// The generics of the helper and the lifetimes of its visitor, `'de` outlives the borrowed keys and values.
+    fn lifetimes(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        match (self.key_life.clone(), self.val_life.clone()) {
+            (None, None) => (quote!('de), quote!()),
+            (None, Some(v_lt)) => (quote!('de: #v_lt,#v_lt), quote!(#v_lt)),
+            (Some(k_lt), None) => (quote!('de: #k_lt,#k_lt), quote!(#k_lt)),
+            (Some(k_lt), Some(v_lt)) => {
+                if k_lt == v_lt {
+                    (quote!('de: #k_lt,#k_lt), quote!(#k_lt))
+                } else {
+                    (quote!('de: #v_lt+ #k_lt,#v_lt,#k_lt), quote!(#k_lt,#v_lt))
+                }
+            }
+        }
+    }
+}
+
+/// The deserialize helper of a `flat_regex` field.
+struct DeHelper<'a> {
+    ctx: FieldCtx<'a>,
+    types: &'a EntryTypes<'a>,
+    /// the name of the helper
+    fun: Ident,
+    /// the function returning the matcher of `value_flat_regex`
+    value_matcher_fn: Ident,
+    /// the prefix of the fields in the item, the siblings whose keys `only` skips
+    prefix: &'a str,
+    limits: &'a RegexLimits,
+    /// binds `key_str` to the key as it's matched
+    bind_key: proc_macro2::TokenStream,
+    /// the matcher function nested in the helper, empty if it's shared with the serialize helper
+    nested_matcher: proc_macro2::TokenStream,
+    /// the function returning the matcher of the field
+    matcher_fn: &'a Ident,
+    /// gets the matcher `re` in the visitor
+    regex_new: proc_macro2::TokenStream,
+    skip_errors: bool,
+    error_on_collision: bool,
+    optional: bool,
+    /// converts the collection into the field type
+    wrap: Option<proc_macro2::TokenStream>,
+    item_args: &'a ItemArgs,
+}
+
+impl DeHelper<'_> {
+    /// Generates the helper for `#[serde(deserialize_with)]`.
+    fn generate(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let ty = &flat_field.ty;
+        let coll_ty = self.types.coll_ty;
+        let item = &self.types.item;
+        let (with_lifetime, visitor_lifetime) = self.types.lifetimes();
+        let fun = &self.fun;
+        let nested_matcher = &self.nested_matcher;
+        let support_types = self.support_types();
+        let visit_map = self.visit_map();
+        let (visit_null, visit_seq, deserialize_fn) = self.visit_null_or_seq();
+        // points a value type which isn't `Deserialize` out at the field instead of the generated visitor
+        let value_bound = if flat_field.value_into.is_present()
+            || flat_field.value_as.is_some()
+            || flat_field.value_serde_as.is_some()
+            || flat_field.captures.is_present()
+            || flat_field.value_from_capture.is_some()
+        {
+            quote!()
+        } else {
+            quote_spanned!(syn::spanned::Spanned::span(item)=> #item: serde::Deserialize<'de>,)
+        };
+        // nested items don't see the bound of the helper, repeating it keeps the visitor from reporting it again.
+        // The bound of a borrowed value would tie the lifetimes of the visitor together.
+        let visitor_bound = if self.types.val_life.is_none() {
+            value_bound.clone()
+        } else {
+            quote!()
+        };
+        let deserialize = self.deserialize(&deserialize_fn);
+        // the field and pattern reported by `from_value_strict`
+        let listed_pattern = if let Some(listed) = self.ctx.pattern.listed() {
+            quote!(std::option::Option::Some(#listed))
+        } else {
+            quote!(std::option::Option::None)
+        };
+        if self.is_plain() {
+            return self.generate_plain(&value_bound, &listed_pattern);
+        }
+        let deserialize =
+            self.item_args
+                .track_field(self.ctx.field_path, &listed_pattern, &deserialize);
+        // the helpers are only called from the derived `Deserialize` impl, so inlining them doesn't duplicate code
+        quote!(
+        #[inline]
+        fn #fun<#with_lifetime, D,>(
+            deserializer: D,
+        ) -> std::result::Result<#ty, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+            #value_bound {
+            use serde::de::Error;
+
+            #nested_matcher
+
+            struct RegexVisitor<#visitor_lifetime>(std::marker::PhantomData<#coll_ty>);
+
+            #support_types
+
+            impl<#with_lifetime> serde::de::Visitor<'de> for RegexVisitor<#visitor_lifetime>
+            where
+                #visitor_bound
+            {
+                type Value = #coll_ty;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(formatter, "a {}",stringify!(#coll_ty))
+                }
+
+                #visit_map
+
+                #visit_null
+
+                #visit_seq
+            }
+        #deserialize
+        }
+        )
+    }
+
+    /// The field only matches keys and stores the entries as they are, which the visitor of the runtime crate does.
+    fn is_plain(&self) -> bool {
+        let FieldCtx {
+            flat_field,
+            key_access,
+            indexed,
+            ..
+        } = self.ctx;
+        matches!(key_access, KeyAccess::AsRef)
+            && !indexed
+            && !self.optional
+            && !self.skip_errors
+            && !self.error_on_collision
+            && !self.types.key_parse
+            // the bound on a borrowed value type in the `where` clause of the helper would also constrain the key
+            && self.types.val_life.is_none()
+            && !flat_field.borrow_key.is_present()
+            && flat_field.key_wrap.is_none()
+            && flat_field.replace.is_none()
+            && !flat_field.auto_prefix.is_present()
+            && !flat_field.urldecode.is_present()
+            && flat_field.key_from_capture.is_none()
+            && flat_field.value_from_capture.is_none()
+            && !flat_field.captures.is_present()
+            && flat_field.value_variant.is_none()
+            && !flat_field.value_into.is_present()
+            && flat_field.value_as.is_none()
+            && flat_field.value_serde_as.is_none()
+            && flat_field.value_pattern.is_none()
+            && !flat_field.invert.is_present()
+            && !flat_field.multimap.is_present()
+            && !flat_field.reserve_hint.is_present()
+            && flat_field.stop_key.is_none()
+            && flat_field.dedup_key.is_none()
+            && !flat_field.only.is_present()
+            && !flat_field.trace_skipped.is_present()
+    }
+
+    /// Generates the helper of a plain field, which deserializes it with the visitor of the runtime crate.
+    fn generate_plain(
+        &self,
+        value_bound: &proc_macro2::TokenStream,
+        listed_pattern: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let ty = &flat_field.ty;
+        let coll_ty = self.types.coll_ty;
+        let (key, value) = (&self.types.key, &self.types.value);
+        let (with_lifetime, _) = self.types.lifetimes();
+        let fun = &self.fun;
+        let nested_matcher = &self.nested_matcher;
+        let regex_new = self
+            .ctx
+            .pattern
+            .matcher_new(self.matcher_fn, &quote!(D::Error));
+        let is_match = self.ctx.is_match(&quote!(key_str));
+        let allow_null = flat_field.allow_null.is_present();
+        let map_or_empty_seq = flat_field.map_or_empty_seq.is_present();
+        let take = if let Some(take) = flat_field.take {
+            quote!(std::option::Option::Some(#take))
+        } else {
+            quote!(std::option::Option::None)
+        };
+        let wrap = &self.wrap;
+        let krate = self.item_args.krate();
+        let deserialize = self.item_args.track_field(
+            self.ctx.field_path,
+            listed_pattern,
+            &quote!({
+                let re = #regex_new;
+                let options = #krate::__FlatOptions {
+                    allow_null: #allow_null,
+                    map_or_empty_seq: #map_or_empty_seq,
+                    take: #take,
+                };
+                let collection = #krate::__deserialize_flat::<#coll_ty, #key, #value, D>(
+                    deserializer,
+                    options,
+                    &std::concat!("a ", std::stringify!(#coll_ty)),
+                    |key_str: &str| #is_match,
+                );
+                collection #wrap
+            }),
+        );
+        quote!(
+        #[inline]
+        fn #fun<#with_lifetime, D,>(
+            deserializer: D,
+        ) -> std::result::Result<#ty, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+            #value_bound {
+            use serde::de::Error;
+
+            #nested_matcher
+
+            #deserialize
+        }
+        )
+    }
+
+    /// Generates the types the visitor needs besides itself, e.g. the seed of `value_variant`.
+    fn support_types(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let krate = self.item_args.krate();
+        let key_str_type = if flat_field.borrow_key.is_present() {
+            borrowed_key_str()
+        } else if self.types.int_key {
+            int_key_string()
+        } else {
+            quote!()
+        };
+        let variant_seed_type = if flat_field.value_variant.is_some() {
+            variant_seed()
+        } else {
+            quote!()
+        };
+        let or_skip_type = if self.skip_errors {
+            or_skip()
+        } else {
+            quote!()
+        };
+        let value_flat_seed_type = if let Some(value_regex) = &flat_field.value_pattern {
+            let seed = value_flat_seed(&krate);
+            let matcher = Pattern::Lit(value_regex.clone()).cached_matcher(
+                &self.value_matcher_fn,
+                self.limits,
+                &krate,
+            );
+            quote!(#seed #matcher)
+        } else {
+            quote!()
+        };
+        let siblings_matcher = if flat_field.only.is_present() {
+            Pattern::Rest(self.prefix.to_string()).cached_matcher(
+                &self.siblings_matcher_fn(),
+                self.limits,
+                &krate,
+            )
+        } else {
+            quote!()
+        };
+        quote!(
+            #key_str_type
+
+            #variant_seed_type
+
+            #or_skip_type
+
+            #value_flat_seed_type
+
+            #siblings_matcher
+        )
+    }
+
+    /// Generates skipping the value of the non-matching key `key`.
+    ///
+    /// Values of non-matching keys still have to be consumed unless the map is buffered by flatten,
+    /// with `only` the first key not matching any `flat_regex` field of the item is an error instead of being skipped.
+    fn skip_value(&self, key: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let FieldCtx {
+            flat_field,
+            field_name,
+            ..
+        } = self.ctx;
+        let trace = trace_skipped(flat_field, field_name, key, &self.item_args.krate());
+        if flat_field.only.is_present() {
+            let siblings_matcher_fn = self.siblings_matcher_fn();
+            let siblings_fun = rest_siblings_fn(self.prefix);
+            return quote!(
+                if !#siblings_fun(#siblings_matcher_fn().map_err(A::Error::custom)?, &#key) {
+                    return std::result::Result::Err(A::Error::custom(format!(
+                        "key `{}` doesn't match the pattern of `{}`",
+                        #key, #field_name
+                    )));
+                }
+                #trace
+                map.next_value::<serde::de::IgnoredAny>()?;
+            );
+        }
+        quote!(
+            #trace
+            map.next_value::<serde::de::IgnoredAny>()?;
+        )
+    }
+
+    /// The function returning the `RegexSet` of the patterns of all `flat_regex` fields of the item, for `only`.
+    fn siblings_matcher_fn(&self) -> Ident {
+        Ident::new(&format!("{}_siblings", self.fun), Span::call_site())
+    }
+
+    /// Generates the check of `contiguous` that every index up to the largest captured one is present.
+    fn check_contiguous(&self) -> proc_macro2::TokenStream {
+        let field_name = self.ctx.field_name;
+        if self.ctx.flat_field.contiguous.is_present() {
+            quote!(
+                if let std::option::Option::Some(idx) = seen.iter().position(|seen| !seen) {
+                    return std::result::Result::Err(A::Error::custom(format!(
+                        "missing key for index {} of `{}`",
+                        idx, #field_name
+                    )));
+                }
+            )
+        } else {
+            quote!()
+        }
+    }
+
+    /// Generates `visit_map` of the visitor.
+    fn visit_map(&self) -> proc_macro2::TokenStream {
+        if self.ctx.flat_field.array.is_present() {
+            self.visit_array()
+        } else if self.ctx.flat_field.into_indexed_vec.is_present() {
+            self.visit_indexed_vec()
+        } else {
+            self.visit_collection()
+        }
+    }
+
+    /// Generates `visit_map` for `array`, which places the values by the captured index.
+    fn visit_array(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let value = &self.types.value;
+        let de_regex_new = &self.regex_new;
+        let len = array_inner(&flat_field.ty).1;
+        let skip_key = self.skip_value(&quote!(key));
+        let capture = flat_field.key_from_capture.as_ref().unwrap();
+        let check_missing = if flat_field.allow_missing.is_present() {
+            quote!(std::result::Result::Ok(
+                entries.map(std::option::Option::unwrap_or_default)
+            ))
+        } else {
+            quote!(
+                if let std::option::Option::Some(idx) = entries.iter().position(std::option::Option::is_none) {
+                    return std::result::Result::Err(A::Error::custom(format!("missing key for index {}", idx)));
+                }
+                std::result::Result::Ok(entries.map(std::option::Option::unwrap))
+            )
+        };
+        quote!(
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+                where A: serde::de::MapAccess<'de>,
+            {
+                let re = #de_regex_new;
+                let mut entries: [std::option::Option<#value>; #len] = std::array::from_fn(|_| std::option::Option::None);
+                while let std::option::Option::Some(key) = map.next_key::<std::string::String>()? {
+                    if let std::option::Option::Some(captures) = re.captures(&key) {
+                        let idx = captures
+                            .name(#capture)
+                            .ok_or_else(|| A::Error::custom(format!("key `{}` has no capture `{}`", key, #capture)))?
+                            .as_str()
+                            .parse::<usize>()
+                            .map_err(A::Error::custom)?;
+                        let len = entries.len();
+                        let entry = entries.get_mut(idx).ok_or_else(|| {
+                            A::Error::custom(format!("index {} of key `{}` is out of range for length {}", idx, key, len))
+                        })?;
+                        *entry = std::option::Option::Some(map.next_value::<#value>()?);
+                    } else {
+                        #skip_key
+                    }
+                }
+                #check_missing
+            }
+        )
+    }
+
+    /// Generates `visit_map` for `into_indexed_vec`, which places the values by the captured index.
+    fn visit_indexed_vec(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let value = &self.types.value;
+        let de_regex_new = &self.regex_new;
+        let skip_key = self.skip_value(&quote!(key));
+        let capture = flat_field.key_from_capture.as_ref().unwrap();
+        let entries_len = index_len(flat_field, value, &quote!(key), self.ctx.field_name);
+        let (seen_init, record_index) = if flat_field.contiguous.is_present() {
+            (
+                quote!(let mut seen: std::vec::Vec<bool> = std::vec::Vec::new();),
+                // `len` is bounded by `max_index` of the entries
+                quote!(
+                    if idx >= seen.len() {
+                        seen.resize(len, false);
+                    }
+                    seen[idx] = true;
+                ),
+            )
+        } else {
+            (quote!(), quote!())
+        };
+        let check_contiguous = self.check_contiguous();
+        quote!(
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+                where A: serde::de::MapAccess<'de>,
+            {
+                let re = #de_regex_new;
+                #seen_init
+                let mut entries: std::vec::Vec<#value> = std::vec::Vec::new();
+                while let std::option::Option::Some(key) = map.next_key::<std::string::String>()? {
+                    if let std::option::Option::Some(captures) = re.captures(&key) {
+                        let idx = captures
+                            .name(#capture)
+                            .ok_or_else(|| A::Error::custom(format!("key `{}` has no capture `{}`", key, #capture)))?
+                            .as_str()
+                            .parse::<usize>()
+                            .map_err(A::Error::custom)?;
+                        #entries_len
+                        if idx >= entries.len() {
+                            entries.resize_with(len, std::default::Default::default);
+                        }
+                        entries[idx] = map.next_value::<#value>()?;
+                        #record_index
+                    } else {
+                        #skip_key
+                    }
+                }
+                #check_contiguous
+                std::result::Result::Ok(entries)
+            }
+        )
+    }
+
+    /// Generates `visit_map` for collections of key value entries.
+    fn visit_collection(&self) -> proc_macro2::TokenStream {
+        let de_regex_new = &self.regex_new;
+        let (choose_variant, next_value) = self.next_value();
+        let rewrite_key = self.rewrite_key();
+        let store = self.store();
+        let check_collision = self.check_collision();
+        let (take_init, take_check, take_count) = self.take();
+        let (stop_init, stop_check) = self.stop_key();
+        let (seen_init, record_index) = self.record_index();
+        let (dedup_init, dedup_check) = self.dedup_key();
+        let check_contiguous = self.check_contiguous();
+        let insert = quote!(
+            #take_check
+            #dedup_check
+            #rewrite_key
+            let val = #next_value;
+            #check_collision
+            #record_index
+            #store
+            #take_count
+        );
+        let visit_keys = self.visit_keys(&choose_variant, &stop_check, &insert);
+        quote!(
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+                where A: serde::de::MapAccess<'de>,
+            {
+                let re = #de_regex_new;
+                // the collection is only created on the first match
+                let mut collection: std::option::Option<Self::Value> = std::option::Option::None;
+                #take_init
+                #stop_init
+                #seen_init
+                #dedup_init
+                #visit_keys
+                #check_contiguous
+                std::result::Result::Ok(collection.unwrap_or_default())
+            }
+        )
+    }
+
+    /// Generates choosing the variant of `value_variant` and reading the value of a matching key.
+    fn next_value(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let flat_field = self.ctx.flat_field;
+        let item = &self.types.item;
+        let value = &self.types.value;
+        let skip_errors = self.skip_errors;
+        let value_matcher_fn = &self.value_matcher_fn;
+        match &flat_field.value_variant {
+            Some(fun) => (
+                quote!(let variant: &'static str = #fun(&key_str);),
+                quote!(map.next_value_seed(VariantSeed::<#item>(variant, std::marker::PhantomData))?),
+            ),
+            // the value is parsed from a capture group of the key, the value in the input is ignored
+            None if flat_field.value_from_capture.is_some() => {
+                let capture = flat_field.value_from_capture.as_ref().unwrap();
+                (
+                    quote!(),
+                    quote!({
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                        captures
+                            .name(#capture)
+                            .ok_or_else(|| A::Error::custom(format!("key `{}` has no capture `{}`", key_str, #capture)))?
+                            .as_str()
+                            .parse::<#item>()
+                            .map_err(A::Error::custom)?
+                    }),
+                )
+            }
+            None if flat_field.captures.is_present() => (
+                quote!(),
+                quote!({
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                    re.captures(&key_str)
+                        .unwrap()
+                        .iter()
+                        .skip(1)
+                        .map(|group| group.map_or_else(std::string::String::new, |group| group.as_str().to_string()))
+                        .collect::<#value>()
+                }),
+            ),
+            None if flat_field.value_as.is_some() || flat_field.value_serde_as.is_some() => {
+                (quote!(), self.converted_value())
+            }
+            // the value is a map itself, of which only the entries matching `value_flat_regex` are kept
+            None if flat_field.value_pattern.is_some() => (
+                quote!(),
+                quote!(map.next_value_seed(ValueFlatSeed::<#item>(
+                    #value_matcher_fn().map_err(A::Error::custom)?,
+                    std::marker::PhantomData,
+                ))?),
+            ),
+            None if flat_field.value_into.is_present() && skip_errors => (
+                quote!(),
+                quote!(match map.next_value::<OrSkip<std::string::String>>()?.0 {
+                    std::option::Option::Some(val) => std::convert::Into::<#item>::into(val),
+                    std::option::Option::None => continue,
+                }),
+            ),
+            None if flat_field.value_into.is_present() => (
+                quote!(),
+                quote!(std::convert::Into::<#item>::into(map.next_value::<std::string::String>()?)),
+            ),
+            None if skip_errors => (
+                quote!(),
+                quote!(match map.next_value::<OrSkip<#item>>()?.0 {
+                    std::option::Option::Some(val) => val,
+                    std::option::Option::None => continue,
+                }),
+            ),
+            None => (quote!(), quote!(map.next_value::<#item>()?)),
+        }
+    }
+
+    /// Generates reading a value of `value_as` or `value_serde_as`, which is converted after reading.
+    fn converted_value(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let item = &self.types.item;
+        // the value is read as the wire type of `value_as` and converted, failed conversions are skipped with `on_error = "skip"`
+        if let Some(wire) = &flat_field.value_as {
+            let convert = flat_field.value_convert.as_ref().unwrap();
+            let (read, on_convert_error) = if self.skip_errors {
+                (
+                    quote!(match map.next_value::<OrSkip<#wire>>()?.0 {
+                        std::option::Option::Some(val) => val,
+                        std::option::Option::None => continue,
+                    }),
+                    quote!(std::result::Result::Err(_) => continue),
+                )
+            } else {
+                (
+                    quote!(map.next_value::<#wire>()?),
+                    quote!(std::result::Result::Err(e) => return std::result::Result::Err(A::Error::custom(e))),
+                )
+            };
+            return quote!(match #convert(#read) {
+                std::result::Result::Ok(val) => val,
+                #on_convert_error,
+            });
+        }
+        // the value is deserialized with the `DeserializeAs` of `serde_with`
+        let as_ty = flat_field.value_serde_as.as_ref().unwrap();
+        let wrapped = quote!(serde_with::de::DeserializeAsWrap::<#item, #as_ty>);
+        if self.skip_errors {
+            quote!(match map.next_value::<OrSkip<#wrapped>>()?.0 {
+                std::option::Option::Some(val) => val.into_inner(),
+                std::option::Option::None => continue,
+            })
+        } else {
+            quote!(map.next_value::<#wrapped>()?.into_inner())
+        }
+    }
+
+    /// Generates the statements rewriting the matched key before it's stored, e.g. with `replace`.
+    fn rewrite_key(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let key = &self.types.key;
+        // with `key_wrap` the key is read as `String` and wrapped after matching
+        let is_string_key = flat_field.key_wrap.is_some()
+            || matches!(key, GenericArgument::Type(Type::Path(p)) if p.path.segments.last().unwrap().ident == "String");
+        let rewrite_key = flat_field.replace.as_ref().map(|template| {
+            if !is_string_key {
+                abort!(key, "`replace` needs `String` keys");
+            }
+            quote!(let key: std::string::String = re.replace(&key, #template).into_owned();)
+        });
+        // with `urldecode` the decoded key is stored unless `keep_encoded_key` is set
+        let decode_key =
+            flat_field.urldecode.is_present() && !flat_field.keep_encoded_key.is_present();
+        if flat_field.urldecode.is_present() && self.types.int_key {
+            abort!(key, "`urldecode` needs `String` keys");
+        }
+        // with `value_from_capture` the key is parsed from the capture group of `key_from_capture`
+        let rewrite_key = match (&flat_field.value_from_capture, &flat_field.key_from_capture) {
+            (Some(_), Some(capture)) => Some(quote!(
+                let captures = re.captures(&key_str).unwrap();
+                let key = captures
+                    .name(#capture)
+                    .ok_or_else(|| A::Error::custom(format!("key `{}` has no capture `{}`", key_str, #capture)))?
+                    .as_str()
+                    .parse::<#key>()
+                    .map_err(A::Error::custom)?;
+            )),
+            _ => rewrite_key,
+        };
+        let rewrite_key = if flat_field.auto_prefix.is_present() || decode_key {
+            if !is_string_key {
+                abort!(key, "`auto_prefix` and `urldecode` need `String` keys");
+            }
+            Some(quote!(let key: std::string::String = key_str.to_owned();))
+        } else {
+            rewrite_key
+        };
+        let wrap_key = flat_field
+            .key_wrap
+            .as_ref()
+            .map(|fun| quote!(let key: #key = #fun(key);));
+        quote!(#rewrite_key #wrap_key)
+    }
+
+    /// Generates storing the entry in the collection, which is created on the first match.
+    fn store(&self) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let coll_ty = self.types.coll_ty;
+        let (key, value) = (&self.types.key, &self.types.value);
+        let entry = &self.types.entry;
+        // the entries as the collection stores them, with `multimap` `value` is already the `Vec<V>` of a key
+        let entry_types = if flat_field.invert.is_present() {
+            quote!(#value, #key)
+        } else {
+            quote!(#key, #value)
+        };
+        // the collection is created through its type as written, which also covers `crate::` paths, aliases and hashers
+        let new_collection = if flat_field.reserve_hint.is_present() {
+            quote!(|| {
+                let mut collection = <#coll_ty as std::default::Default>::default();
+                if let std::option::Option::Some(hint) = map.size_hint() {
+                    // like serde, don't trust the hint for more than 1MiB
+                    let max = 1024 * 1024 / std::cmp::max(std::mem::size_of::<(#entry_types)>(), 1);
+                    collection.reserve(std::cmp::min(hint.saturating_add(1), max));
+                }
+                collection
+            })
+        } else {
+            quote!(<#coll_ty as std::default::Default>::default)
+        };
+        if flat_field.multimap.is_present() {
+            quote!(
+                collection
+                    .get_or_insert_with(#new_collection)
+                    .entry(key)
+                    .or_default()
+                    .push(val);
+            )
+        } else {
+            quote!(
+                collection
+                    .get_or_insert_with(#new_collection)
+                    .extend(std::iter::once(#entry));
+            )
+        }
+    }
+
+    /// Generates the check of `on_collision = "error"` that a stored key isn't stored again, e.g. after `replace`.
+    fn check_collision(&self) -> proc_macro2::TokenStream {
+        if !self.error_on_collision {
+            return quote!();
+        }
+        let field_name = self.ctx.field_name;
+        let stored_key = if self.ctx.flat_field.invert.is_present() {
+            quote!(val)
+        } else {
+            quote!(key)
+        };
+        quote!(
+            if collection.as_ref().is_some_and(|collection| collection.contains_key(&#stored_key)) {
+                return std::result::Result::Err(A::Error::custom(format!(
+                    "key `{}` of `{}` collides with an earlier key",
+                    key_str, #field_name
+                )));
+            }
+        )
+    }
+
+    /// Generates counting the taken entries of `take`, the remaining matches are drained like non-matching keys.
+    fn take(
+        &self,
+    ) -> (
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+    ) {
+        match self.ctx.flat_field.take {
+            Some(take) => (
+                quote!(let mut taken: usize = 0;),
+                quote!(
+                    if taken == #take {
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                        continue;
+                    }
+                ),
+                quote!(taken += 1;),
+            ),
+            None => (quote!(), quote!(), quote!()),
+        }
+    }
+
+    /// Generates the check of `stop_key`, the sentinel and all keys after it are drained.
+    fn stop_key(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        match &self.ctx.flat_field.stop_key {
+            Some(stop_key) => (
+                quote!(let mut stopped = false;),
+                quote!(
+                    if stopped || key_str == #stop_key {
+                        stopped = true;
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                        continue;
+                    }
+                ),
+            ),
+            None => (quote!(), quote!()),
+        }
+    }
+
+    /// Generates recording the captured indices of `contiguous`, which are checked for gaps after the last key.
+    fn record_index(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let flat_field = self.ctx.flat_field;
+        let seen_len = index_len(
+            flat_field,
+            quote!(bool),
+            &quote!(key_str),
+            self.ctx.field_name,
+        );
+        match &flat_field.key_from_capture {
+            Some(capture) if flat_field.contiguous.is_present() => (
+                quote!(let mut seen: std::vec::Vec<bool> = std::vec::Vec::new();),
+                quote!(
+                    let idx = re
+                        .captures(&key_str)
+                        .and_then(|captures| captures.name(#capture))
+                        .ok_or_else(|| A::Error::custom(format!("key `{}` has no capture `{}`", key_str, #capture)))?
+                        .as_str()
+                        .parse::<usize>()
+                        .map_err(A::Error::custom)?;
+                    #seen_len
+                    if idx >= seen.len() {
+                        seen.resize(len, false);
+                    }
+                    seen[idx] = true;
+                ),
+            ),
+            _ => (quote!(), quote!()),
+        }
+    }
+
+    /// Generates the check of `dedup_key`, a key is skipped if its normalized form was seen before, the first one wins.
+    fn dedup_key(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        match &self.ctx.flat_field.dedup_key {
+            Some(fun) => (
+                quote!(let mut dedup_keys = std::collections::HashSet::<std::string::String>::new();),
+                quote!(
+                    if !dedup_keys.insert(#fun(&key_str)) {
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                        continue;
+                    }
+                ),
+            ),
+            None => (quote!(), quote!()),
+        }
+    }
+
+    /// Generates the loop over the keys of the map, which `insert`s the entries of matching keys.
+    fn visit_keys(
+        &self,
+        choose_variant: &proc_macro2::TokenStream,
+        stop_check: &proc_macro2::TokenStream,
+        insert: &proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let flat_field = self.ctx.flat_field;
+        let key = &self.types.key;
+        let skip_value = self.skip_value(&quote!(key_str));
+        if flat_field.borrow_key.is_present() {
+            let borrowed_is_match = self.ctx.is_match(&quote!(&key_str));
+            let borrowed_key = if flat_field.key_parse.is_present() {
+                quote!(let key = key_str.parse::<#key>().map_err(A::Error::custom)?;)
+            } else {
+                quote!(
+                    let key = match key_str {
+                        std::borrow::Cow::Borrowed(s) => <#key as serde::Deserialize>::deserialize(
+                            serde::de::value::BorrowedStrDeserializer::<A::Error>::new(s),
+                        )?,
+                        std::borrow::Cow::Owned(s) => <#key as serde::Deserialize>::deserialize(
+                            serde::de::value::StringDeserializer::<A::Error>::new(s),
+                        )?,
+                    };
+                )
+            };
+            return quote!(
+                while let std::option::Option::Some(KeyStr(key_str)) = map.next_key::<KeyStr<'de>>()? {
+                    #stop_check
+                    if #borrowed_is_match {
+                        #choose_variant
+                        #borrowed_key
+                        #insert
+                    } else {
+                        #skip_value
+                    }
+                }
+            );
+        }
+        let is_match = self.ctx.is_match(&quote!(key_str));
+        if self.types.key_parse {
+            let (key_string, key_string_ty) = if self.types.int_key {
+                (quote!(IntKey(key_string)), quote!(IntKey))
+            } else {
+                (quote!(key_string), quote!(std::string::String))
+            };
+            return quote!(
+                while let std::option::Option::Some(#key_string) = map.next_key::<#key_string_ty>()? {
+                    let key_str: &str = &key_string;
+                    #stop_check
+                    if #is_match {
+                        #choose_variant
+                        let key = key_str.parse::<#key>().map_err(A::Error::custom)?;
+                        #insert
+                    } else {
+                        #skip_value
+                    }
+                }
+            );
+        }
+        let raw_key = if flat_field.key_wrap.is_some() || flat_field.value_from_capture.is_some() {
+            quote!(std::string::String)
+        } else {
+            quote!(#key)
+        };
+        let de_key_access = &self.bind_key;
+        quote!(
+            while let std::option::Option::Some(key) = map.next_key::<#raw_key>()? {
+                #de_key_access
+                #stop_check
+                if #is_match {
+                    #choose_variant
+                    #insert
+                } else {
+                    #skip_value
+                }
+            }
+        )
+    }
+
+    /// Generates `visit_unit`, `visit_none` and `visit_seq` of the visitor with `allow_null` and `map_or_empty_seq`,
+    /// and the `deserialize_*` method handing them to the visitor.
+    fn visit_null_or_seq(
+        &self,
+    ) -> (
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+    ) {
+        let FieldCtx {
+            field, flat_field, ..
+        } = self.ctx;
+        let visit_null = if flat_field.allow_null.is_present() {
+            if flat_field.array.is_present() {
+                abort!(field, "`allow_null` is not supported for `array`");
+            }
+            quote!(
+                fn visit_unit<E>(self) -> std::result::Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    std::result::Result::Ok(std::default::Default::default())
+                }
+
+                fn visit_none<E>(self) -> std::result::Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    std::result::Result::Ok(std::default::Default::default())
+                }
+            )
+        } else {
+            quote!()
+        };
+        // some APIs send an empty map as `[]`
+        let visit_seq = if flat_field.map_or_empty_seq.is_present() {
+            if flat_field.array.is_present() {
+                abort!(field, "`map_or_empty_seq` is not supported for `array`");
+            }
+            quote!(
+                fn visit_seq<S>(self, mut seq: S) -> std::result::Result<Self::Value, S::Error>
+                where
+                    S: serde::de::SeqAccess<'de>,
+                {
+                    if seq.next_element::<serde::de::IgnoredAny>()?.is_some() {
+                        return std::result::Result::Err(S::Error::invalid_length(
+                            1,
+                            &"a map or an empty sequence",
+                        ));
+                    }
+                    std::result::Result::Ok(std::default::Default::default())
+                }
+            )
+        } else {
+            quote!()
+        };
+        // formats only hand `null` or sequences to the visitor with `deserialize_any`
+        let deserialize_fn =
+            if flat_field.allow_null.is_present() || flat_field.map_or_empty_seq.is_present() {
+                quote!(deserialize_any)
+            } else {
+                quote!(deserialize_map)
+            };
+        (visit_null, visit_seq, deserialize_fn)
+    }
+
+    /// Generates deserializing the field with the visitor, an `Option` field is `None` for `null`.
+    fn deserialize(&self, deserialize_fn: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        if !self.optional {
+            let wrap = &self.wrap;
+            return quote!(deserializer.#deserialize_fn(RegexVisitor(std::marker::PhantomData))#wrap);
+        }
+        let coll_ty = self.types.coll_ty;
+        let (with_lifetime, visitor_lifetime) = self.types.lifetimes();
+        let empty_is_none = self.ctx.flat_field.empty_is_none.is_present().then(
+            || quote!(.map(|collection| collection.filter(|collection| !collection.is_empty()))),
+        );
+        quote!({
+            struct OptionVisitor<#visitor_lifetime>(std::marker::PhantomData<#coll_ty>);
+
+            impl<#with_lifetime> serde::de::Visitor<'de> for OptionVisitor<#visitor_lifetime> {
+                type Value = std::option::Option<#coll_ty>;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(formatter, "a {} or null", stringify!(#coll_ty))
+                }
+
+                fn visit_unit<E>(self) -> std::result::Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    std::result::Result::Ok(std::option::Option::None)
+                }
+
+                fn visit_none<E>(self) -> std::result::Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    std::result::Result::Ok(std::option::Option::None)
+                }
+
+                fn visit_some<D>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+                {
+                    deserializer.#deserialize_fn(RegexVisitor(std::marker::PhantomData)).map(std::option::Option::Some)
+                }
+
+                fn visit_map<A>(self, map: A) -> std::result::Result<Self::Value, A::Error>
+                where
+                    A: serde::de::MapAccess<'de>,
+                {
+                    serde::de::Visitor::visit_map(RegexVisitor(std::marker::PhantomData), map).map(std::option::Option::Some)
+                }
+
+                fn visit_seq<A>(self, seq: A) -> std::result::Result<Self::Value, A::Error>
+                where
+                    A: serde::de::SeqAccess<'de>,
+                {
+                    serde::de::Visitor::visit_seq(RegexVisitor(std::marker::PhantomData), seq).map(std::option::Option::Some)
+                }
+            }
+
+            deserializer.deserialize_any(OptionVisitor(std::marker::PhantomData))#empty_is_none
+        })
+    }
+
+    /// Generates extending the field in place for `merge_from_value`, if its collection supports it.
+    fn merge(&self) -> Option<proc_macro2::TokenStream> {
+        let flat_field = self.ctx.flat_field;
+        // collections which can be extended in place, borrowed entries can't outlive the value
+        let mergeable = !self.ctx.indexed
+            && !self.optional
+            && flat_field.wrap.is_none()
+            && flat_field.collect_into.is_none()
+            && flat_field.try_collect_into.is_none()
+            && !flat_field.multimap.is_present()
+            && !flat_field.captures.is_present()
+            && self.types.key_life.is_none()
+            && self.types.val_life.is_none();
+        if !mergeable {
+            return None;
+        }
+        let member = if let Some(ident) = &flat_field.ident {
+            quote!(#ident)
+        } else {
+            quote!(0)
+        };
+        let fun = &self.fun;
+        Some(quote!(
+            std::iter::Extend::extend(&mut self.#member, #fun(&value)?);
+        ))
+    }
+}
+
+/// Rewrites a field with `routes = ["...", ...]`, a tuple of collections where the entries of keys matching
+/// the n-th pattern go into the n-th collection.
+///
+/// The patterns are matched with one `RegexSet` and the first matching pattern wins, keys are deserialized
+/// as `String` and then into the key type of their collection.
+#[allow(clippy::too_many_arguments)]
+fn routes_field(
+    field: &Field,
+    flat_field: &FlatRegex,
+    routes: &syn::ExprArray,
+    prefix: &str,
+    owner: &str,
+    field_path: &str,
+    container: &SerdeContainer,
+    item_args: &ItemArgs,
+    generated: &mut Generated,
+) -> proc_macro2::TokenStream {
+    let Some(ident) = &flat_field.ident else {
+        abort!(field, "`routes` is only supported on named fields")
+    };
+    let ty = &flat_field.ty;
+    let Type::Tuple(tuple) = ty else {
+        abort!(ty, "`routes` needs a tuple of collections, e.g. `(HashMap<String, V>, HashMap<String, V>)`")
+    };
+    let patterns = routes_patterns(routes, item_args.base.as_ref(), field_path);
+    if patterns.len() != tuple.elems.len() {
+        abort!(
+            routes,
+            "`routes` has {} patterns for a tuple of {} collections",
+            patterns.len(),
+            tuple.elems.len()
+        );
+    }
+    let field_name = ident.to_string();
+    let ident = syn::ext::IdentExt::unraw(ident);
+    let name_hash = stable_hash(&format!("{owner}::{ident}={}", routes.to_token_stream()));
+    let mut arms = Vec::new();
+    let mut ser_entries = Vec::new();
+    for (idx, coll_ty) in tuple.elems.iter().enumerate() {
+        let (key, value) = inner(coll_ty);
+        if lifetime(&key).is_some() || lifetime(&value).is_some() {
+            abort!(coll_ty, "`routes` doesn't support borrowed keys or values");
+        }
+        let member = syn::Index::from(idx);
+        arms.push(quote!(
+            std::option::Option::Some(#idx) => {
+                let key = <#key as serde::Deserialize>::deserialize(
+                    serde::de::IntoDeserializer::<A::Error>::into_deserializer(key),
+                )?;
+                let val = map.next_value::<#value>()?;
+                std::iter::Extend::extend(&mut routes.#member, std::iter::once((key, val)));
+            }
+        ));
+        ser_entries.push(quote!(
+            for (key, val) in &value.#member {
+                map.serialize_entry(key, val)?;
+            }
+        ));
+    }
+    for pattern in &patterns {
+        generated
+            .patterns
+            .push(quote!((#field_name, std::option::Option::Some(#pattern))));
+        generated.prefix_patterns.push((
+            prefix.to_string(),
+            KeyTransform::default(),
+            quote!(#pattern),
+        ));
+    }
+    let expecting = format!("a map with keys matching the routes of `{field_path}`");
+    let mut serde_attr = vec![quote!(flatten)];
+    if !container.serialize_only() {
+        let fun_name = format!("__with_regex_{prefix}_{ident}_{name_hash}");
+        let r = Ident::new(&fun_name, Span::call_site());
+        generated.with_fn.push(routes_deserialize(
+            &r, ty, &patterns, &arms, &expecting, field_path, item_args,
+        ));
+        serde_attr.push(quote!(deserialize_with = #fun_name));
+    }
+    if container.serialize {
+        let ser_fun_name = format!("__with_regex_ser_{prefix}_{ident}_{name_hash}");
+        let ser = Ident::new(&ser_fun_name, Span::call_site());
+        generated.with_fn.push(quote!(
+            #[allow(clippy::type_complexity)]
+            fn #ser<S>(value: &#ty, serializer: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                use serde::ser::SerializeMap;
+
+                let mut map = serializer.serialize_map(std::option::Option::None)?;
+                #(#ser_entries)*
+                map.end()
+            }
+        ));
+        serde_attr.push(quote!(serialize_with = #ser_fun_name));
+    }
+    let vis = &flat_field.vis;
+    let attr = field.attrs.iter().filter(|a| !is_flat_regex(a));
+    let ident = &flat_field.ident;
+    quote!(
+        #[serde(#(#serde_attr),*)]
+        #(#attr)*
+        #vis #ident: #ty
+    )
+}
+
+/// The patterns of `routes` with the `base` prepended, validated at compile time.
+fn routes_patterns(
+    routes: &syn::ExprArray,
+    base: Option<&syn::LitStr>,
+    field_path: &str,
+) -> Vec<syn::LitStr> {
+    lit_strs(routes, "routes")
+        .into_iter()
+        .map(|lit| {
+            let lit = match base {
+                Some(base) => syn::LitStr::new(&(base.value() + &lit.value()), lit.span()),
+                None => lit,
+            };
+            if let Err(e) = regex::Regex::new(&lit.value()) {
+                abort!(lit, "invalid regex in {}: {}", field_path, e);
+            }
+            lit
+        })
+        .collect()
+}
+
+/// Generates the deserialize helper `fun` of a `routes` field of type `ty`, `arms` route the index of the first
+/// matching pattern to its collection.
+fn routes_deserialize(
+    fun: &Ident,
+    ty: &Type,
+    patterns: &[syn::LitStr],
+    arms: &[proc_macro2::TokenStream],
+    expecting: &str,
+    field_path: &str,
+    item_args: &ItemArgs,
+) -> proc_macro2::TokenStream {
+    let krate = item_args.krate();
+    let deserialize = item_args.track_field(
+        field_path,
+        &quote!(std::option::Option::None),
+        &quote!(deserializer.deserialize_map(RoutesVisitor)),
+    );
+    quote!(
+        // the signature is given by the tuple of the field
+        #[allow(clippy::type_complexity)]
+        #[inline]
+        fn #fun<'de, D>(deserializer: D) -> std::result::Result<#ty, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            use serde::de::Error;
+
+            fn matcher() -> std::result::Result<&'static #krate::__regex::RegexSet, #krate::__regex::Error> {
+                static MATCHER: std::sync::OnceLock<#krate::__regex::RegexSet> = std::sync::OnceLock::new();
+                if let std::option::Option::Some(matcher) = MATCHER.get() {
+                    return std::result::Result::Ok(matcher);
+                }
+                let matcher = #krate::__regex::RegexSet::new([#(#patterns),*])?;
+                std::result::Result::Ok(MATCHER.get_or_init(|| matcher))
+            }
+
+            struct RoutesVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for RoutesVisitor {
+                type Value = #ty;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str(#expecting)
+                }
+
+                fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+                    where A: serde::de::MapAccess<'de>,
+                {
+                    let set = matcher().map_err(A::Error::custom)?;
+                    let mut routes: #ty = std::default::Default::default();
+                    while let std::option::Option::Some(key) = map.next_key::<std::string::String>()? {
+                        match set.matches(&key).iter().next() {
+                            #(#arms)*
+                            _ => {
+                                map.next_value::<serde::de::IgnoredAny>()?;
+                            }
+                        }
+                    }
+                    std::result::Result::Ok(routes)
+                }
+            }
+
+            #deserialize
+        }
+    )
+}
+
+/// Logs a skipped key at trace level with `tracing` if `trace_skipped` is set and the `tracing` feature is enabled.
+fn trace_skipped(
+    flat_field: &FlatRegex,
+    field_name: &str,
+    key: &proc_macro2::TokenStream,
+    krate: &syn::Path,
+) -> proc_macro2::TokenStream {
+    if cfg!(feature = "tracing") && flat_field.trace_skipped.is_present() {
+        quote!(#krate::__tracing::trace!("skipped key `{}` not matching flat_regex field `{}`", #key, #field_name);)
+    } else {
+        quote!()
+    }
+}
+
+/// A value which is `None` if it failed to deserialize, used by `on_error = "skip"`.
+///
+/// Only sound for buffered values like those of flattened fields, a failed value of a streaming
+/// deserializer may be left partially consumed.
+fn or_skip() -> proc_macro2::TokenStream {
+    quote!(
+        struct OrSkip<V>(std::option::Option<V>);
+
+        impl<'de, V: serde::Deserialize<'de>> serde::Deserialize<'de> for OrSkip<V> {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                std::result::Result::Ok(OrSkip(V::deserialize(deserializer).ok()))
+            }
+        }
+    )
+}
+
+/// A `DeserializeSeed` deserializing a map value into the collection `C`, keeping only the entries whose keys match
+/// the regex, used by `value_flat_regex`.
+fn value_flat_seed(krate: &syn::Path) -> proc_macro2::TokenStream {
+    quote!(
+        struct ValueFlatSeed<C>(&'static #krate::__regex::Regex, std::marker::PhantomData<C>);
+
+        impl<'de, C, K, V> serde::de::DeserializeSeed<'de> for ValueFlatSeed<C>
+        where
+            C: std::default::Default
+                + std::iter::Extend<(K, V)>
+                + std::iter::IntoIterator<Item = (K, V)>,
+            K: serde::Deserialize<'de> + std::convert::AsRef<str>,
+            V: serde::Deserialize<'de>,
+        {
+            type Value = C;
+
+            fn deserialize<D>(self, deserializer: D) -> std::result::Result<C, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_map(self)
+            }
+        }
+
+        impl<'de, C, K, V> serde::de::Visitor<'de> for ValueFlatSeed<C>
+        where
+            C: std::default::Default
+                + std::iter::Extend<(K, V)>
+                + std::iter::IntoIterator<Item = (K, V)>,
+            K: serde::Deserialize<'de> + std::convert::AsRef<str>,
+            V: serde::Deserialize<'de>,
+        {
+            type Value = C;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "a map with keys matching `{}`", self.0.as_str())
+            }
+
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<C, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut collection = C::default();
+                while let std::option::Option::Some(key) = map.next_key::<K>()? {
+                    if self.0.is_match(key.as_ref()) {
+                        let val = map.next_value::<V>()?;
+                        std::iter::Extend::extend(&mut collection, std::iter::once((key, val)));
+                    } else {
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                    }
+                }
+                std::result::Result::Ok(collection)
+            }
+        }
+    )
+}
+
+/// A `DeserializeSeed` deserializing a value as the given variant of an externally tagged enum, used by `value_variant`.
+fn variant_seed() -> proc_macro2::TokenStream {
+    quote!(
+        struct VariantSeed<V>(&'static str, std::marker::PhantomData<V>);
+
+        impl<'de, V: serde::Deserialize<'de>> serde::de::DeserializeSeed<'de> for VariantSeed<V> {
+            type Value = V;
+
+            fn deserialize<D>(self, deserializer: D) -> std::result::Result<V, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                V::deserialize(VariantDeserializer(self.0, deserializer))
+            }
+        }
+
+        struct VariantDeserializer<D>(&'static str, D);
+
+        impl<'de, D: serde::Deserializer<'de>> serde::Deserializer<'de> for VariantDeserializer<D> {
+            type Error = D::Error;
+
+            fn deserialize_any<V>(self, visitor: V) -> std::result::Result<V::Value, D::Error>
+            where
+                V: serde::de::Visitor<'de>,
+            {
+                visitor.visit_enum(self)
+            }
+
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+                bytes byte_buf option unit unit_struct newtype_struct seq tuple
+                tuple_struct map struct enum identifier ignored_any
+            }
+        }
+
+        impl<'de, D: serde::Deserializer<'de>> serde::de::EnumAccess<'de> for VariantDeserializer<D> {
+            type Error = D::Error;
+            type Variant = VariantValue<D>;
+
+            fn variant_seed<S>(
+                self,
+                seed: S,
+            ) -> std::result::Result<(S::Value, Self::Variant), D::Error>
+            where
+                S: serde::de::DeserializeSeed<'de>,
+            {
+                let variant =
+                    seed.deserialize(serde::de::value::StrDeserializer::<D::Error>::new(self.0))?;
+                std::result::Result::Ok((variant, VariantValue(self.1)))
+            }
+        }
+
+        struct VariantValue<D>(D);
+
+        impl<'de, D: serde::Deserializer<'de>> serde::de::VariantAccess<'de> for VariantValue<D> {
+            type Error = D::Error;
+
+            fn unit_variant(self) -> std::result::Result<(), D::Error> {
+                <serde::de::IgnoredAny as serde::Deserialize>::deserialize(self.0).map(|_| ())
+            }
+
+            fn newtype_variant_seed<T>(self, seed: T) -> std::result::Result<T::Value, D::Error>
+            where
+                T: serde::de::DeserializeSeed<'de>,
+            {
+                seed.deserialize(self.0)
+            }
+
+            fn tuple_variant<V>(
+                self,
+                len: usize,
+                visitor: V,
+            ) -> std::result::Result<V::Value, D::Error>
+            where
+                V: serde::de::Visitor<'de>,
+            {
+                self.0.deserialize_tuple(len, visitor)
+            }
+
+            fn struct_variant<V>(
+                self,
+                fields: &'static [&'static str],
+                visitor: V,
+            ) -> std::result::Result<V::Value, D::Error>
+            where
+                V: serde::de::Visitor<'de>,
+            {
+                self.0.deserialize_struct("", fields, visitor)
+            }
+        }
+    )
+}
+
+/// A key which is a string or an integer in the input as its decimal string, used for integer key types.
+fn int_key_string() -> proc_macro2::TokenStream {
+    quote!(
+        struct IntKey(std::string::String);
+
+        impl<'de> serde::Deserialize<'de> for IntKey {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct IntKeyVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for IntKeyVisitor {
+                    type Value = IntKey;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("an integer or string key")
+                    }
+
+                    fn visit_u64<E>(self, v: u64) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(IntKey(v.to_string()))
+                    }
+
+                    fn visit_i64<E>(self, v: i64) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(IntKey(v.to_string()))
+                    }
+
+                    fn visit_u128<E>(self, v: u128) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(IntKey(v.to_string()))
+                    }
+
+                    fn visit_i128<E>(self, v: i128) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(IntKey(v.to_string()))
+                    }
+
+                    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(IntKey(v.to_owned()))
+                    }
+
+                    fn visit_string<E>(
+                        self,
+                        v: std::string::String,
+                    ) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(IntKey(v))
+                    }
+                }
+
+                // the key may be either, which only self-describing formats can tell
+                deserializer.deserialize_any(IntKeyVisitor)
+            }
+        }
+    )
+}
+
+/// A key which is borrowed from the input if the format allows it, used by `borrow_key`.
+fn borrowed_key_str() -> proc_macro2::TokenStream {
+    quote!(
+        struct KeyStr<'a>(std::borrow::Cow<'a, str>);
+
+        impl<'de> serde::Deserialize<'de> for KeyStr<'de> {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct KeyStrVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for KeyStrVisitor {
+                    type Value = KeyStr<'de>;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("a string key")
+                    }
+
+                    fn visit_borrowed_str<E>(
+                        self,
+                        v: &'de str,
+                    ) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(KeyStr(std::borrow::Cow::Borrowed(v)))
+                    }
+
+                    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(KeyStr(std::borrow::Cow::Owned(v.to_owned())))
+                    }
+
+                    fn visit_string<E>(
+                        self,
+                        v: std::string::String,
+                    ) -> std::result::Result<Self::Value, E> {
+                        std::result::Result::Ok(KeyStr(std::borrow::Cow::Owned(v)))
+                    }
+                }
+
+                deserializer.deserialize_str(KeyStrVisitor)
+            }
+        }
+    )
+}
@@ -0,0 +1,78 @@
+use regex::Regex;
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Deserializer};
+
+use crate::{ExtendSeed, FlatCollection};
+
+/// Accumulates the matching entries of many maps into one collection, e.g. of a stream of small JSON objects.
+///
+/// The pattern is compiled once when the accumulator is created and used for every document.
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// use serde_flat_regex::FlatRegexAccumulator;
+///
+/// let mut acc = FlatRegexAccumulator::<BTreeMap<String, u32>>::new(r"^port_\d+$").unwrap();
+/// for line in [r#"{"port_0": 100, "id": 1}"#, r#"{"port_1": 10, "id": 2}"#] {
+///     let mut deserializer = serde_json::Deserializer::from_str(line);
+///     acc.extend_from_deserializer(&mut deserializer).unwrap();
+/// }
+/// assert_eq!(acc.into_inner().len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FlatRegexAccumulator<C> {
+    regex: Regex,
+    collection: C,
+}
+
+impl<C: Default> FlatRegexAccumulator<C> {
+    /// Creates an accumulator with an empty collection, keeping the entries whose keys match `pattern`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the regex crate if `pattern` is invalid.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Self::with_collection(pattern, C::default())
+    }
+}
+
+impl<C> FlatRegexAccumulator<C> {
+    /// Creates an accumulator adding to an existing collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the regex crate if `pattern` is invalid.
+    pub fn with_collection(pattern: &str, collection: C) -> Result<Self, regex::Error> {
+        Ok(FlatRegexAccumulator {
+            regex: Regex::new(pattern)?,
+            collection,
+        })
+    }
+
+    /// Adds the entries of the map in `deserializer` whose keys match the pattern.
+    ///
+    /// Later entries with the same key replace earlier ones if the collection does, like `HashMap`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the deserializer, the entries added before it failed are kept.
+    pub fn extend_from_deserializer<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        C: FlatCollection + Extend<(C::Key, C::Value)>,
+        C::Key: Deserialize<'de> + AsRef<str>,
+        C::Value: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        ExtendSeed::new(&self.regex, &mut self.collection).deserialize(deserializer)
+    }
+
+    /// The entries accumulated so far.
+    pub fn collection(&self) -> &C {
+        &self.collection
+    }
+
+    /// Returns the accumulated collection.
+    pub fn into_inner(self) -> C {
+        self.collection
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer};
+
+/// The start of the message of a field error, which [`from_value_strict`] recognizes.
+const FIELD_ERROR: &str = "flat_regex field `";
+
+/// A deserialization error of [`from_value_strict`], with the `flat_regex` field that failed if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A `flat_regex` field failed, e.g. a matching value had the wrong type or a key couldn't be accessed as `&str`.
+    Field {
+        /// The path of the field, e.g. `RouterStatus::lanport_status`.
+        field: String,
+        /// The pattern of the field, `None` for patterns only known at runtime and `matcher` functions.
+        pattern: Option<String>,
+        /// The message of the deserializer.
+        message: String,
+    },
+    /// Any other error of the deserializer.
+    Deserialize(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Field {
+                field,
+                pattern: Some(pattern),
+                message,
+            } => write!(f, "{FIELD_ERROR}{field}` (pattern {pattern:?}): {message}"),
+            Error::Field {
+                field,
+                pattern: None,
+                message,
+            } => write!(f, "{FIELD_ERROR}{field}`: {message}"),
+            Error::Deserialize(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Parses the message of an error, which is a field error if it starts like the [`Display`](fmt::Display) of
+    /// [`Error::Field`].
+    fn parse(message: String) -> Self {
+        Self::parse_field(&message).unwrap_or(Error::Deserialize(message))
+    }
+
+    fn parse_field(message: &str) -> Option<Self> {
+        let (field, rest) = message.strip_prefix(FIELD_ERROR)?.split_once('`')?;
+        let (pattern, rest) = match rest.strip_prefix(" (pattern ") {
+            Some(rest) => {
+                let (pattern, rest) = parse_debug_str(rest)?;
+                (Some(pattern), rest.strip_prefix(')')?)
+            }
+            None => (None, rest),
+        };
+        Some(Error::Field {
+            field: field.to_string(),
+            pattern,
+            message: rest.strip_prefix(": ")?.to_string(),
+        })
+    }
+}
+
+/// Parses a string as written by its `Debug` impl at the start of `s`, returning it and the rest of `s`.
+fn parse_debug_str(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.chars();
+    let mut parsed = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some((parsed, chars.as_str())),
+            '\\' => parsed.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                'u' => {
+                    let rest = chars.as_str().strip_prefix('{')?;
+                    let (hex, _) = rest.split_once('}')?;
+                    chars = rest[hex.len() + 1..].chars();
+                    char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+                }
+                escaped => escaped,
+            }),
+            c => parsed.push(c),
+        }
+    }
+    None
+}
+
+/// Deserializes `T` from any deserializer, e.g. `&serde_json::Value`, `serde_json::Deserializer` or `bson::Bson`,
+/// and reports which `flat_regex` field failed.
+///
+/// Only the fields of items with `#[flat_regex(track_errors)]` are reported. Their helpers name the field and its
+/// pattern in the message of the error, the same message the deserializer returns without `from_value_strict`.
+///
+/// # Errors
+///
+/// Returns [`Error::Field`] if the error came from a tracked `flat_regex` field, otherwise [`Error::Deserialize`].
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_flat_regex::{flat_regex, from_value_strict, Error};
+/// use std::collections::HashMap;
+///
+/// #[flat_regex(track_errors)]
+/// #[derive(Debug, Deserialize)]
+/// struct RouterStatus {
+///     #[flat_regex(regex = r"^lanportstatus_\d+$")]
+///     lanport_status: HashMap<String, bool>,
+/// }
+///
+/// let json = r#"{"lanportstatus_0": "UP"}"#;
+/// let err = from_value_strict::<RouterStatus, _>(&mut serde_json::Deserializer::from_str(json)).unwrap_err();
+/// assert!(matches!(err, Error::Field { field, .. } if field == "RouterStatus::lanport_status"));
+/// ```
+pub fn from_value_strict<'de, T, D>(deserializer: D) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map_err(|e| Error::parse(e.to_string()))
+}
+
+/// Names the flat_regex field in the error of a generated helper with `track_errors`, called with its body.
+///
+/// The error of a nested field already names the innermost field and is kept.
+#[doc(hidden)]
+pub fn __track_flat_field<T, E: serde::de::Error>(
+    field: &'static str,
+    pattern: Option<&'static str>,
+    deserialize: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    deserialize().map_err(|e| {
+        let message = e.to_string();
+        if message.starts_with(FIELD_ERROR) {
+            return e;
+        }
+        E::custom(Error::Field {
+            field: field.to_string(),
+            pattern: pattern.map(str::to_string),
+            message,
+        })
+    })
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::flattener::deserialize_matching;
+use crate::FlatCollection;
+
+/// A regex pattern as type, used by [`Flattened`].
+///
+/// Usually implemented with [`pattern!`](crate::pattern).
+pub trait Pattern {
+    /// The regex keys have to match.
+    fn pattern() -> &'static str;
+
+    /// The compiled [`pattern`](Pattern::pattern), compiled once and shared by all deserializations.
+    ///
+    /// [`pattern!`](crate::pattern) keeps it in a static of the type, a manual impl does the same:
+    ///
+    /// ```
+    /// use std::sync::OnceLock;
+    /// use regex::Regex;
+    ///
+    /// struct LanPort;
+    ///
+    /// impl serde_flat_regex::Pattern for LanPort {
+    ///     fn pattern() -> &'static str {
+    ///         r"^lanportstatus_\d+$"
+    ///     }
+    ///
+    ///     fn regex() -> Result<&'static Regex, regex::Error> {
+    ///         static REGEX: OnceLock<Regex> = OnceLock::new();
+    ///         if let Some(regex) = REGEX.get() {
+    ///             return Ok(regex);
+    ///         }
+    ///         let regex = Regex::new(Self::pattern())?;
+    ///         Ok(REGEX.get_or_init(|| regex))
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the regex crate if the pattern is invalid.
+    fn regex() -> Result<&'static Regex, regex::Error>;
+}
+
+/// Declares a zero sized type implementing [`Pattern`].
+///
+/// ```
+/// serde_flat_regex::pattern!(pub LanPort = r"^lanportstatus_\d+$");
+/// ```
+#[macro_export]
+macro_rules! pattern {
+    ($vis:vis $name:ident = $pattern:expr) => {
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        $vis struct $name;
+
+        impl $crate::Pattern for $name {
+            fn pattern() -> &'static str {
+                $pattern
+            }
+
+            fn regex() -> ::std::result::Result<&'static $crate::__regex::Regex, $crate::__regex::Error> {
+                static REGEX: ::std::sync::OnceLock<$crate::__regex::Regex> = ::std::sync::OnceLock::new();
+                if let ::std::option::Option::Some(regex) = REGEX.get() {
+                    return ::std::result::Result::Ok(regex);
+                }
+                let regex = $crate::__regex::Regex::new(<Self as $crate::Pattern>::pattern())?;
+                ::std::result::Result::Ok(REGEX.get_or_init(|| regex))
+            }
+        }
+    };
+}
+
+/// A collection which only keeps the entries whose keys match the [`Pattern`] `P`.
+///
+/// Used with `#[serde(flatten)]` it is an alternative to the [`flat_regex`](crate::flat_regex) macro.
+/// The collection must implement `Extend<(K,V)> + Default` and iterate over `(K,V)`, keys must implement `AsRef<str>`.
+/// Deserialization matches keys with [`Pattern::regex`], which is only compiled once, also for many values.
+/// Serialization writes all entries of the collection.
+pub struct Flattened<C, P> {
+    inner: C,
+    pattern: PhantomData<P>,
+}
+
+impl<C, P> Flattened<C, P> {
+    /// Wraps a collection.
+    pub fn new(inner: C) -> Self {
+        Flattened {
+            inner,
+            pattern: PhantomData,
+        }
+    }
+
+    /// Returns the wrapped collection.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C, P> Deref for Flattened<C, P> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C, P> DerefMut for Flattened<C, P> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<C: Default, P> Default for Flattened<C, P> {
+    fn default() -> Self {
+        Flattened::new(C::default())
+    }
+}
+
+impl<C: Clone, P> Clone for Flattened<C, P> {
+    fn clone(&self) -> Self {
+        Flattened::new(self.inner.clone())
+    }
+}
+
+impl<C: fmt::Debug, P> fmt::Debug for Flattened<C, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<C: PartialEq, P> PartialEq for Flattened<C, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<C: Serialize, P> Serialize for Flattened<C, P> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+impl<'de, C, P> Deserialize<'de> for Flattened<C, P>
+where
+    C: FlatCollection + Default + Extend<(C::Key, C::Value)>,
+    C::Key: Deserialize<'de> + AsRef<str>,
+    C::Value: Deserialize<'de>,
+    P: Pattern,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_flat_regex::<C, P, D>(deserializer).map(Flattened::new)
+    }
+}
+
+/// Deserializes a map into `C`, keeping only the entries whose keys match the [`Pattern`] `P`.
+///
+/// For `#[serde(deserialize_with)]` on a flattened field of the plain collection type, without a wrapper or the macro.
+/// Like [`Flattened`] it matches with the cached [`Pattern::regex`].
+///
+/// # Errors
+///
+/// An invalid pattern is a deserialization error.
+///
+/// ```
+/// # use std::collections::HashMap;
+/// use serde_flat_regex::{deserialize_flat_regex, pattern};
+/// use serde::Deserialize;
+///
+/// pattern!(LanPort = r"^lanportstatus_\d+$");
+///
+/// #[derive(Debug,Deserialize)]
+/// struct RouterStatus {
+///     online: bool,
+///     #[serde(flatten, deserialize_with = "deserialize_flat_regex::<_, LanPort, _>")]
+///     lanport_status: HashMap<String,bool>,
+/// }
+///
+/// let json = r#"{"online": true, "lanportstatus_0": true, "lanportspeed_0": 100}"#;
+/// let status: RouterStatus = serde_json::from_str(json).unwrap();
+/// assert_eq!(status.lanport_status.len(), 1);
+/// ```
+pub fn deserialize_flat_regex<'de, C, P, D>(deserializer: D) -> Result<C, D::Error>
+where
+    C: FlatCollection + Default + Extend<(C::Key, C::Value)>,
+    C::Key: Deserialize<'de> + AsRef<str>,
+    C::Value: Deserialize<'de>,
+    P: Pattern,
+    D: Deserializer<'de>,
+{
+    let regex = P::regex().map_err(D::Error::custom)?;
+    deserialize_matching(regex, deserializer)
+}
@@ -0,0 +1,486 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+
+use regex::{Regex, RegexBuilder};
+use serde::de::{
+    DeserializeSeed, Error, Expected, IgnoredAny, MapAccess, SeqAccess, Unexpected, Visitor,
+};
+use serde::{Deserialize, Deserializer};
+
+/// A collection of `(Key, Value)` entries to flatten into.
+///
+/// Implemented for every collection iterating over `(K,V)`, e.g. `HashMap<K,V>`, `BTreeMap<K,V>` or `Vec<(K,V)>`.
+pub trait FlatCollection {
+    /// The key type of the entries.
+    type Key;
+    /// The value type of the entries.
+    type Value;
+}
+
+impl<C, K, V> FlatCollection for C
+where
+    C: IntoIterator<Item = (K, V)>,
+{
+    type Key = K;
+    type Value = V;
+}
+
+/// Builder for regex flattened deserialization at runtime, without the [`flat_regex`](crate::flat_regex) macro.
+///
+/// ```
+/// # use std::collections::HashMap;
+/// use serde_flat_regex::RegexFlattener;
+///
+/// let json = r#"{"Port_0": true, "port_1": false, "speed": 100}"#;
+/// let mut deserializer = serde_json::Deserializer::from_str(json);
+/// let ports: HashMap<String, bool> = RegexFlattener::new()
+///     .pattern(r"^port_\d+$")
+///     .case_insensitive(true)
+///     .deserialize_map(&mut deserializer)
+///     .unwrap();
+/// assert_eq!(ports.len(), 2);
+/// ```
+///
+/// The regex is compiled on first use and reused by later calls, changing the options of the regex compiles it again.
+///
+/// The builder only covers a field which matches keys and stores the entries as they are, the macro uses the same
+/// visitor for such fields. `pattern` is the `regex` of the field, `case_insensitive` the `(?i)` flag, and
+/// `size_limit`, `dfa_size_limit`, `unmatched`, `allow_null`, `map_or_empty_seq` and `take` behave like the field
+/// options of the same name. All other options are only supported by the [`flat_regex`](crate::flat_regex) macro,
+/// e.g. `glob`, `literals`, `matcher`, `rest` and `routes`, the key and value options like `key_from_capture` or
+/// `value_as`, `array`, `on_collision`, `on_error`, the item options and serialization. The macro generates its own
+/// visitor for fields with any of them, so the builder isn't a runtime replacement for the macro.
+#[derive(Debug, Clone, Default)]
+pub struct RegexFlattener {
+    pattern: Option<String>,
+    case_insensitive: bool,
+    size_limit: Option<usize>,
+    dfa_size_limit: Option<usize>,
+    unmatched: bool,
+    options: __FlatOptions,
+    regex: OnceLock<Regex>,
+}
+
+impl RegexFlattener {
+    /// Creates a builder without a pattern.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the regex keys have to match.
+    #[must_use]
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = Some(pattern.into());
+        self.regex = OnceLock::new();
+        self
+    }
+
+    /// Matches the pattern case insensitive.
+    #[must_use]
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.case_insensitive = yes;
+        self.regex = OnceLock::new();
+        self
+    }
+
+    /// Limits the size of the compiled regex, like the `size_limit` field attribute.
+    #[must_use]
+    pub fn size_limit(mut self, bytes: usize) -> Self {
+        self.size_limit = Some(bytes);
+        self.regex = OnceLock::new();
+        self
+    }
+
+    /// Limits the cache of the lazy DFA of the regex, like the `dfa_size_limit` field attribute.
+    #[must_use]
+    pub fn dfa_size_limit(mut self, bytes: usize) -> Self {
+        self.dfa_size_limit = Some(bytes);
+        self.regex = OnceLock::new();
+        self
+    }
+
+    /// Keeps the entries whose keys don't match the pattern, like the `unmatched` field attribute.
+    #[must_use]
+    pub fn unmatched(mut self, yes: bool) -> Self {
+        self.unmatched = yes;
+        self
+    }
+
+    /// Deserializes `null` to an empty collection, like the `allow_null` field attribute.
+    #[must_use]
+    pub fn allow_null(mut self, yes: bool) -> Self {
+        self.options.allow_null = yes;
+        self
+    }
+
+    /// Deserializes an empty sequence to an empty collection, like the `map_or_empty_seq` field attribute.
+    #[must_use]
+    pub fn map_or_empty_seq(mut self, yes: bool) -> Self {
+        self.options.map_or_empty_seq = yes;
+        self
+    }
+
+    /// Keeps only the first `n` matching entries, like the `take` field attribute.
+    #[must_use]
+    pub fn take(mut self, n: usize) -> Self {
+        self.options.take = Some(n);
+        self
+    }
+
+    /// Compiles the pattern with the configured options.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the regex crate if the pattern is invalid.
+    pub fn build_regex(&self) -> Result<Regex, regex::Error> {
+        let mut builder = RegexBuilder::new(self.pattern.as_deref().unwrap_or_default());
+        builder.case_insensitive(self.case_insensitive);
+        if let Some(size_limit) = self.size_limit {
+            builder.size_limit(size_limit);
+        }
+        if let Some(dfa_size_limit) = self.dfa_size_limit {
+            builder.dfa_size_limit(dfa_size_limit);
+        }
+        builder.build()
+    }
+
+    /// Deserializes a map into `C`, keeping only the entries whose keys match the pattern.
+    ///
+    /// # Errors
+    ///
+    /// A missing or invalid pattern is a deserialization error.
+    pub fn deserialize_map<'de, C, D>(&self, deserializer: D) -> Result<C, D::Error>
+    where
+        C: FlatCollection + Default + Extend<(C::Key, C::Value)>,
+        C::Key: Deserialize<'de> + AsRef<str>,
+        C::Value: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let regex = self.regex().map_err(D::Error::custom)?;
+        let unmatched = self.unmatched;
+        __deserialize_flat(deserializer, self.options, &Matching(regex), |key| {
+            regex.is_match(key) != unmatched
+        })
+    }
+
+    /// Returns a [`DeserializeSeed`] adding the entries whose keys match the pattern to an existing collection,
+    /// e.g. to merge several payloads into one map.
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// use serde::de::DeserializeSeed;
+    /// use serde_flat_regex::RegexFlattener;
+    ///
+    /// let flattener = RegexFlattener::new().pattern(r"^port_\d+$");
+    /// let mut ports: HashMap<String, bool> = HashMap::new();
+    /// for json in [r#"{"port_0": true, "x": 1}"#, r#"{"port_1": false}"#] {
+    ///     let mut deserializer = serde_json::Deserializer::from_str(json);
+    ///     flattener.extend_into(&mut ports).deserialize(&mut deserializer).unwrap();
+    /// }
+    /// assert_eq!(ports.len(), 2);
+    /// ```
+    pub fn extend_into<'a, C>(&'a self, collection: &'a mut C) -> ExtendSeed<'a, C> {
+        ExtendSeed {
+            regex: self.regex(),
+            unmatched: self.unmatched,
+            options: self.options,
+            collection,
+        }
+    }
+
+    /// The compiled regex, built on first use.
+    fn regex(&self) -> Result<&Regex, String> {
+        if self.pattern.is_none() {
+            return Err("RegexFlattener needs a pattern".to_string());
+        }
+        if let Some(regex) = self.regex.get() {
+            return Ok(regex);
+        }
+        let regex = self.build_regex().map_err(|e| e.to_string())?;
+        Ok(self.regex.get_or_init(|| regex))
+    }
+}
+
+/// The options of the visitor besides matching the keys, set by [`RegexFlattener`] and the helpers of the
+/// [`flat_regex`](crate::flat_regex) macro.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct __FlatOptions {
+    /// `null` is an empty collection.
+    pub allow_null: bool,
+    /// An empty sequence is an empty collection.
+    pub map_or_empty_seq: bool,
+    /// Only the first matching entries are kept.
+    pub take: Option<usize>,
+}
+
+/// Deserializes a map into `C`, keeping only the entries whose keys `matcher` accepts.
+///
+/// The visitor of [`RegexFlattener`] and of the helpers the macro generates for fields without options changing the
+/// keys or values, `expecting` describes the expected input in errors.
+#[doc(hidden)]
+pub fn __deserialize_flat<'de, C, K, V, D>(
+    deserializer: D,
+    options: __FlatOptions,
+    expecting: &dyn fmt::Display,
+    matcher: impl Fn(&str) -> bool,
+) -> Result<C, D::Error>
+where
+    C: Default + Extend<(K, V)>,
+    K: Deserialize<'de> + AsRef<str>,
+    V: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    let visitor = FlattenVisitor {
+        matcher,
+        options,
+        expecting,
+        entries: PhantomData,
+    };
+    deserialize_with(deserializer, options, visitor)
+}
+
+/// Deserializes a map into `C`, keeping only the entries whose keys match `regex`.
+pub(crate) fn deserialize_matching<'de, C, D>(regex: &Regex, deserializer: D) -> Result<C, D::Error>
+where
+    C: FlatCollection + Default + Extend<(C::Key, C::Value)>,
+    C::Key: Deserialize<'de> + AsRef<str>,
+    C::Value: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    __deserialize_flat(
+        deserializer,
+        __FlatOptions::default(),
+        &Matching(regex),
+        |key| regex.is_match(key),
+    )
+}
+
+fn deserialize_with<'de, D, V>(
+    deserializer: D,
+    options: __FlatOptions,
+    visitor: V,
+) -> Result<V::Value, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Visitor<'de>,
+{
+    // formats only hand `null` or sequences to the visitor with `deserialize_any`
+    if options.allow_null || options.map_or_empty_seq {
+        deserializer.deserialize_any(visitor)
+    } else {
+        deserializer.deserialize_map(visitor)
+    }
+}
+
+/// Describes the input of a visitor matching keys with a regex.
+struct Matching<'a>(&'a Regex);
+
+impl fmt::Display for Matching<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a map with keys matching `{}`", self.0.as_str())
+    }
+}
+
+/// Stores the entries of `map` whose keys `matcher` accepts with `store`, up to `take` entries.
+fn extend<'de, K, V, A>(
+    matcher: impl Fn(&str) -> bool,
+    take: Option<usize>,
+    mut map: A,
+    mut store: impl FnMut(K, V),
+) -> Result<(), A::Error>
+where
+    K: Deserialize<'de> + AsRef<str>,
+    V: Deserialize<'de>,
+    A: MapAccess<'de>,
+{
+    let mut taken: usize = 0;
+    while let Some(key) = map.next_key::<K>()? {
+        if take != Some(taken) && matcher(key.as_ref()) {
+            let val = map.next_value::<V>()?;
+            store(key, val);
+            taken += 1;
+        } else {
+            map.next_value::<IgnoredAny>()?;
+        }
+    }
+    Ok(())
+}
+
+/// Accepts `null` with `allow_null`.
+fn visit_null<E: Error>(options: __FlatOptions, visitor: &dyn Expected) -> Result<(), E> {
+    if options.allow_null {
+        Ok(())
+    } else {
+        Err(E::invalid_type(Unexpected::Unit, visitor))
+    }
+}
+
+/// Accepts an empty sequence with `map_or_empty_seq`, some APIs send an empty map as `[]`.
+fn visit_empty_seq<'de, S: SeqAccess<'de>>(
+    options: __FlatOptions,
+    mut seq: S,
+    visitor: &dyn Expected,
+) -> Result<(), S::Error> {
+    if !options.map_or_empty_seq {
+        return Err(S::Error::invalid_type(Unexpected::Seq, visitor));
+    }
+    if seq.next_element::<IgnoredAny>()?.is_some() {
+        return Err(S::Error::invalid_length(1, &"a map or an empty sequence"));
+    }
+    Ok(())
+}
+
+/// Returns the error of compiling `pattern` with the default limits of the regex crate, e.g. for patterns built at
+/// runtime before they are passed to [`RegexFlattener::pattern`].
+///
+/// This is only the syntax check of a plain `regex` literal of the [`flat_regex`](crate::flat_regex) macro, the
+/// rules of its field options like `size_limit`, `deny_leading_wildcard`, `prefix`/`suffix` or
+/// `ascii_case_insensitive` aren't applied.
+///
+/// # Errors
+///
+/// Returns the error of the regex crate if `pattern` is invalid.
+///
+/// ```
+/// use serde_flat_regex::validate_pattern;
+///
+/// assert!(validate_pattern(r"^port_\d+$").is_ok());
+/// assert!(validate_pattern(r"^port_(\d+$").is_err());
+/// ```
+pub fn validate_pattern(pattern: &str) -> Result<(), regex::Error> {
+    Regex::new(pattern).map(drop)
+}
+
+/// Adds the matching entries of a map to an existing collection, created by [`RegexFlattener::extend_into`].
+#[derive(Debug)]
+pub struct ExtendSeed<'a, C> {
+    regex: Result<&'a Regex, String>,
+    unmatched: bool,
+    options: __FlatOptions,
+    collection: &'a mut C,
+}
+
+impl<'a, C> ExtendSeed<'a, C> {
+    /// Adds the entries of a map whose keys match an already compiled `regex`.
+    pub(crate) fn new(regex: &'a Regex, collection: &'a mut C) -> Self {
+        ExtendSeed {
+            regex: Ok(regex),
+            unmatched: false,
+            options: __FlatOptions::default(),
+            collection,
+        }
+    }
+}
+
+impl<'de, C> DeserializeSeed<'de> for ExtendSeed<'_, C>
+where
+    C: FlatCollection + Extend<(C::Key, C::Value)>,
+    C::Key: Deserialize<'de> + AsRef<str>,
+    C::Value: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let regex = self.regex.clone().map_err(D::Error::custom)?;
+        deserialize_with(
+            deserializer,
+            self.options,
+            ExtendVisitor {
+                regex,
+                unmatched: self.unmatched,
+                options: self.options,
+                collection: self.collection,
+            },
+        )
+    }
+}
+
+struct ExtendVisitor<'a, C> {
+    regex: &'a Regex,
+    unmatched: bool,
+    options: __FlatOptions,
+    collection: &'a mut C,
+}
+
+impl<'de, C> Visitor<'de> for ExtendVisitor<'_, C>
+where
+    C: FlatCollection + Extend<(C::Key, C::Value)>,
+    C::Key: Deserialize<'de> + AsRef<str>,
+    C::Value: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&Matching(self.regex), formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        let (regex, unmatched) = (self.regex, self.unmatched);
+        let collection = self.collection;
+        extend(
+            |key| regex.is_match(key) != unmatched,
+            self.options.take,
+            map,
+            |key, val| collection.extend(std::iter::once((key, val))),
+        )
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, seq: S) -> Result<(), S::Error> {
+        visit_empty_seq(self.options, seq, &self)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<(), E> {
+        visit_null(self.options, &self)
+    }
+
+    fn visit_none<E: Error>(self) -> Result<(), E> {
+        self.visit_unit()
+    }
+}
+
+struct FlattenVisitor<'a, C, K, V, M> {
+    matcher: M,
+    options: __FlatOptions,
+    expecting: &'a dyn fmt::Display,
+    entries: PhantomData<(C, K, V)>,
+}
+
+impl<'de, C, K, V, M> Visitor<'de> for FlattenVisitor<'_, C, K, V, M>
+where
+    C: Default + Extend<(K, V)>,
+    K: Deserialize<'de> + AsRef<str>,
+    V: Deserialize<'de>,
+    M: Fn(&str) -> bool,
+{
+    type Value = C;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.expecting, formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        // the collection is only created on the first match
+        let mut collection: Option<C> = None;
+        extend(self.matcher, self.options.take, map, |key, val| {
+            collection
+                .get_or_insert_with(C::default)
+                .extend(std::iter::once((key, val)));
+        })?;
+        Ok(collection.unwrap_or_default())
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, seq: S) -> Result<Self::Value, S::Error> {
+        visit_empty_seq(self.options, seq, &self).map(|()| C::default())
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+        visit_null(self.options, &self).map(|()| C::default())
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+        self.visit_unit()
+    }
+}
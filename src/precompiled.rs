@@ -0,0 +1,39 @@
+use regex_automata::dfa::{dense::DFA, Automaton};
+use regex_automata::Input;
+
+#[doc(hidden)]
+pub use regex_automata::util::wire::DeserializeError as __DfaDeserializeError;
+
+/// The bytes of a DFA serialized by the macro, aligned for [`DFA::from_bytes`].
+#[doc(hidden)]
+#[repr(C)]
+pub struct __AlignedBytes<B: ?Sized> {
+    pub align: [u32; 0],
+    pub bytes: B,
+}
+
+/// The matcher of a `precompile` field, a DFA built at compile time and embedded in the binary.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct __PrecompiledDfa(DFA<&'static [u32]>);
+
+impl __PrecompiledDfa {
+    /// Deserializes the DFA, which only validates it and doesn't copy the bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `regex-automata` if the bytes aren't a valid DFA for this target.
+    pub fn from_bytes(bytes: &'static __AlignedBytes<[u8]>) -> Result<Self, __DfaDeserializeError> {
+        DFA::from_bytes(&bytes.bytes).map(|(dfa, _)| __PrecompiledDfa(dfa))
+    }
+
+    /// Whether the pattern matches anywhere in `key`, like `Regex::is_match`.
+    #[must_use]
+    pub fn is_match(&self, key: &str) -> bool {
+        // the macro builds the DFA with unanchored start states and without quit bytes, the search can't fail
+        match self.0.try_search_fwd(&Input::new(key).earliest(true)) {
+            Ok(found) => found.is_some(),
+            Err(e) => unreachable!("the search of a precompiled DFA failed: {e}"),
+        }
+    }
+}
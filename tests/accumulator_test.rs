@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+use serde_flat_regex::FlatRegexAccumulator;
+
+#[test]
+fn accumulates_documents() {
+    let mut acc = FlatRegexAccumulator::<BTreeMap<String, u32>>::new(r"^port_\d+$").unwrap();
+    for json in [
+        r#"{"port_0": 100, "id": 1}"#,
+        r#"{"port_1": 10, "speed": 5}"#,
+        r#"{"port_0": 1000, "port_2": 0}"#,
+    ] {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        acc.extend_from_deserializer(&mut deserializer).unwrap();
+    }
+    assert_eq!(
+        acc.into_inner(),
+        BTreeMap::from([
+            ("port_0".to_string(), 1000),
+            ("port_1".to_string(), 10),
+            ("port_2".to_string(), 0),
+        ])
+    );
+}
+
+#[test]
+fn errors_keep_earlier_entries() {
+    let mut acc =
+        FlatRegexAccumulator::with_collection(r"^port_", vec![("port_9".to_string(), 9u32)])
+            .unwrap();
+    let mut deserializer = serde_json::Deserializer::from_str(r#"{"port_0": 1}"#);
+    acc.extend_from_deserializer(&mut deserializer).unwrap();
+    let mut deserializer = serde_json::Deserializer::from_str(r#"{"port_1": "fast"}"#);
+    assert!(acc.extend_from_deserializer(&mut deserializer).is_err());
+    assert_eq!(acc.collection().len(), 2);
+}
+
+#[test]
+fn invalid_pattern() {
+    assert!(FlatRegexAccumulator::<BTreeMap<String, u32>>::new(r"^port_(\d+$").is_err());
+}
@@ -0,0 +1,106 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+use serde_flat_regex::flat_regex;
+
+struct CountingAlloc;
+
+thread_local! {
+    // counted per thread, tests run in parallel
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let res = f();
+    (res, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn borrow_key_allocates_less() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Owned {
+        #[flat_regex(regex = r"^lanportstatus_\d+$")]
+        lanports: HashMap<String, bool>,
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Borrowed {
+        #[flat_regex(regex = r"^lanportstatus_\d+$", borrow_key)]
+        lanports: HashMap<String, bool>,
+    }
+
+    let mut raw = String::from(r#"{"lanportstatus_0": true, "lanportstatus_1": false"#);
+    for i in 0..1000 {
+        write!(raw, r#", "otherfield_{i}": true"#).unwrap();
+    }
+    raw.push('}');
+
+    let (owned, owned_allocs) = count_allocations(|| serde_json::from_str::<Owned>(&raw).unwrap());
+    let (borrowed, borrowed_allocs) =
+        count_allocations(|| serde_json::from_str::<Borrowed>(&raw).unwrap());
+
+    assert_eq!(owned.lanports, borrowed.lanports);
+    assert_eq!(borrowed.lanports.len(), 2);
+    assert!(
+        borrowed_allocs + 1000 <= owned_allocs,
+        "borrow_key: {borrowed_allocs} allocations, default: {owned_allocs} allocations"
+    );
+}
+
+#[test]
+fn regex_compiled_once() {
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+    // the expression is evaluated by the generated matcher each time it builds the regex
+    fn ports_pattern() -> &'static str {
+        BUILDS.fetch_add(1, Ordering::SeqCst);
+        r"^(lanport|wanport)(status|speed)_\d+$"
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Ports {
+        #[flat_regex(regex = ports_pattern(), serialize_matching)]
+        ports: HashMap<String, u32>,
+    }
+
+    let ports: Ports = serde_json::from_str(r#"{"lanportspeed_0": 100, "x": 1}"#).unwrap();
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+    // the serialize helper shares the matcher of the deserialize helper
+    assert_eq!(
+        serde_json::to_string(&ports).unwrap(),
+        r#"{"lanportspeed_0":100}"#
+    );
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+
+    let round_trip = || {
+        let ports: Ports = serde_json::from_str(r#"{"lanportspeed_0": 100, "x": 1}"#).unwrap();
+        serde_json::to_string(&ports).unwrap()
+    };
+    let (first, first_allocs) = count_allocations(round_trip);
+    let (second, second_allocs) = count_allocations(round_trip);
+    assert_eq!(first, second);
+    assert_eq!(first_allocs, second_allocs);
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_flat_regex::{flat_regex, from_value_strict, Error};
+
+#[flat_regex(track_errors)]
+#[derive(Debug, Deserialize)]
+struct RouterStatus {
+    id: u32,
+    #[flat_regex(regex = r"^lanportstatus_\d+$")]
+    lanport_status: HashMap<String, bool>,
+}
+
+#[allow(clippy::ptr_arg)]
+fn ascii(key: &String) -> Result<&str, String> {
+    if key.is_ascii() {
+        Ok(key)
+    } else {
+        Err(format!("key `{key}` isn't ASCII"))
+    }
+}
+
+#[flat_regex(track_errors)]
+#[derive(Debug, Deserialize)]
+struct Names {
+    #[flat_regex(regex = r"^name_\d+$", key_access = "ascii")]
+    names: HashMap<String, String>,
+}
+
+#[test]
+fn value_type_mismatch() {
+    let json = serde_json::json!({"id": 1, "lanportstatus_0": "UP"});
+    let err = from_value_strict::<RouterStatus, _>(&json).unwrap_err();
+    let Error::Field {
+        field,
+        pattern,
+        message,
+    } = &err
+    else {
+        panic!("expected a field error, got {err:?}")
+    };
+    assert_eq!(field, "RouterStatus::lanport_status");
+    assert_eq!(pattern.as_deref(), Some(r"^lanportstatus_\d+$"));
+    assert!(message.contains("invalid type"), "{message}");
+    assert!(err.to_string().starts_with(
+        r#"flat_regex field `RouterStatus::lanport_status` (pattern "^lanportstatus_\\d+$"): "#
+    ));
+}
+
+#[test]
+fn positional_deserializer() {
+    // the deserializer appends the position to the message of the field
+    let json = r#"{"id": 1, "lanportstatus_0": "UP"}"#;
+    let err = serde_json::from_str::<RouterStatus>(json).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("flat_regex field `RouterStatus::lanport_status`"));
+
+    let err = from_value_strict::<RouterStatus, _>(&mut serde_json::Deserializer::from_str(json))
+        .unwrap_err();
+    let Error::Field { field, message, .. } = &err else {
+        panic!("expected a field error, got {err:?}")
+    };
+    assert_eq!(field, "RouterStatus::lanport_status");
+    assert!(message.ends_with("at line 1 column 34"), "{message}");
+}
+
+#[test]
+fn invalid_key_access() {
+    let bson = bson::bson!({"name_0": "eth0"});
+    let names = from_value_strict::<Names, _>(bson::Deserializer::new(bson)).unwrap();
+    assert_eq!(names.names.len(), 1);
+
+    let json = serde_json::json!({"name_0": "eth0", "näme_1": "eth1"});
+    let err = from_value_strict::<Names, _>(&json).unwrap_err();
+    assert_eq!(
+        err,
+        Error::Field {
+            field: "Names::names".to_string(),
+            pattern: Some(r"^name_\d+$".to_string()),
+            message: "key `näme_1` isn't ASCII".to_string(),
+        }
+    );
+}
+
+#[allow(dead_code)]
+#[flat_regex(track_errors)]
+#[derive(Debug, Deserialize)]
+struct Quotes {
+    #[flat_regex(regex = r#"^say_"\w+"`$"#)]
+    said: HashMap<String, bool>,
+}
+
+#[test]
+fn escaped_pattern() {
+    let json = serde_json::json!({r#"say_"hi"`"#: 1});
+    let err = from_value_strict::<Quotes, _>(&json).unwrap_err();
+    assert!(
+        matches!(&err, Error::Field { pattern: Some(pattern), .. } if pattern == r#"^say_"\w+"`$"#),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn other_errors() {
+    let json = serde_json::json!({"lanportstatus_0": true});
+    let err = from_value_strict::<RouterStatus, _>(&json).unwrap_err();
+    assert_eq!(err, Error::Deserialize("missing field `id`".to_string()));
+
+    let status = from_value_strict::<RouterStatus, _>(&serde_json::json!({"id": 1})).unwrap();
+    assert_eq!(status.id, 1);
+    assert!(status.lanport_status.is_empty());
+}
+
+#[flat_regex(track_errors)]
+#[derive(Debug, Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^lanportstatus_\d+$")]
+    lanport_status: HashMap<String, bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Status {
+    Ports(Ports),
+    Raw(HashMap<String, String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Report {
+    status: Status,
+    count: u32,
+}
+
+#[test]
+fn handled_field_error() {
+    let json = serde_json::json!({"status": {"lanportstatus_0": "UP"}, "count": 1});
+    let report = from_value_strict::<Report, _>(&json).unwrap();
+    assert!(matches!(report.status, Status::Raw(raw) if raw["lanportstatus_0"] == "UP"));
+    let json = serde_json::json!({"status": {"lanportstatus_0": true}, "count": 1});
+    let report = from_value_strict::<Report, _>(&json).unwrap();
+    assert!(matches!(report.status, Status::Ports(ports) if ports.lanport_status.len() == 1));
+    assert_eq!(report.count, 1);
+
+    // the error of `Ports` was handled by the untagged enum, it doesn't stick to the later error
+    let json = serde_json::json!({"status": {"lanportstatus_0": "UP"}, "count": "one"});
+    let err = from_value_strict::<Report, _>(&json).unwrap_err();
+    assert!(matches!(err, Error::Deserialize(_)), "{err:?}");
+}
+
+#[allow(dead_code)]
+#[flat_regex(track_errors)]
+#[derive(Debug, Deserialize)]
+struct Lan {
+    #[flat_regex(regex = r"^lan_\d+$")]
+    lan: HashMap<String, bool>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct Interfaces {
+    status: Status,
+    lan: Lan,
+}
+
+#[test]
+fn same_message() {
+    // both fields fail with the same message, the handled error of `Ports` isn't taken for the one of `Lan`
+    let json = serde_json::json!({"status": {"lanportstatus_0": "UP"}, "lan": {"lan_0": "UP"}});
+    let err = from_value_strict::<Interfaces, _>(&json).unwrap_err();
+    assert!(
+        matches!(&err, Error::Field { field, .. } if field == "Lan::lan"),
+        "{err:?}"
+    );
+}
+
+#[allow(dead_code)]
+#[flat_regex(track_errors)]
+#[derive(Debug, Deserialize)]
+struct Devices {
+    #[flat_regex(regex = r"^device_\d+$")]
+    devices: HashMap<String, Ports>,
+}
+
+#[test]
+fn nested_field() {
+    let json = serde_json::json!({"device_0": {"lanportstatus_0": "UP"}});
+    let err = from_value_strict::<Devices, _>(&json).unwrap_err();
+    assert!(
+        matches!(&err, Error::Field { field, .. } if field == "Ports::lanport_status"),
+        "{err:?}"
+    );
+}
+
+use serde_flat_regex as renamed;
+
+#[flat_regex(crate = "renamed", track_errors)]
+#[derive(Debug, Deserialize)]
+struct Renamed {
+    #[flat_regex(regex = r"^lanportstatus_\d+$")]
+    lanport_status: HashMap<String, bool>,
+}
+
+#[test]
+fn renamed_crate() {
+    let json = serde_json::json!({"lanportstatus_0": true});
+    let status = renamed::from_value_strict::<Renamed, _>(&json).unwrap();
+    assert_eq!(status.lanport_status.len(), 1);
+
+    let json = serde_json::json!({"lanportstatus_0": "UP"});
+    let err = renamed::from_value_strict::<Renamed, _>(&json).unwrap_err();
+    assert!(matches!(
+        &err,
+        Error::Field { field, .. } if field == "Renamed::lanport_status"
+    ));
+}
+
+#[flat_regex]
+#[derive(Debug, Deserialize)]
+struct Untracked {
+    #[flat_regex(regex = r"^lanportstatus_\d+$")]
+    lanport_status: HashMap<String, bool>,
+}
+
+#[test]
+fn untracked_field() {
+    let json = serde_json::json!({"lanportstatus_0": "UP"});
+    let err = from_value_strict::<Untracked, _>(&json).unwrap_err();
+    assert!(matches!(err, Error::Deserialize(_)), "{err:?}");
+
+    let json = serde_json::json!({"lanportstatus_0": true});
+    let status = from_value_strict::<Untracked, _>(&json).unwrap();
+    assert_eq!(status.lanport_status.len(), 1);
+}
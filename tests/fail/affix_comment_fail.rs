@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Devices {
+    #[flat_regex(prefix = "dev_", regex = r"(?x) port_\d+ # the port", suffix = "_up")]
+    up: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
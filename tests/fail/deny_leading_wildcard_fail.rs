@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^.*_status$", deny_leading_wildcard)]
+    ports: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
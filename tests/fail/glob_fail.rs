@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(glob = "lanport_[0-9")]
+    ports: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
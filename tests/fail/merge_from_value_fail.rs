@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex(gen_merge_from_value)]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^lanport_\d+$", wrap = "std::sync::Arc::new")]
+    ports: std::sync::Arc<std::collections::HashMap<String, bool>>,
+}
+
+fn main() {}
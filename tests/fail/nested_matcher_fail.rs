@@ -0,0 +1,15 @@
+use serde::Deserialize;
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(Debug, Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^lanport_\d+$")]
+    status: std::collections::HashMap<String, bool>,
+}
+
+fn main() {
+    // the helper is next to the item, the matcher with the same hash is nested in it
+    let _ = __with_regex_Ports_status_9697a4c3::<serde_json::Value>;
+    let _ = __flat_regex_matcher_Ports_status_9697a4c3;
+}
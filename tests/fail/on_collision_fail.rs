@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^port_\d+$", on_collision = "error")]
+    ports: Vec<(String, bool)>,
+}
+
+fn main() {}
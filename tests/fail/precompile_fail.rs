@@ -0,0 +1,11 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    // a Unicode word boundary would make the search of the DFA fail on non-ASCII keys
+    #[flat_regex(regex = r"\bport_\d+\b", precompile)]
+    ports: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
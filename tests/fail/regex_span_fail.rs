@@ -0,0 +1,12 @@
+use serde::Deserialize;
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+struct Foo {
+    id: u32,
+    #[flat_regex(serialize_matching, regex = r"lanport(status|speed_\d+", borrow_key)]
+    rest: std::collections::HashMap<std::string::String, String>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^port_(?P<idx>\d+)$", replace = "$index")]
+    ports: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^port_\d+$", reserve_hint)]
+    ports: std::collections::BTreeMap<String, bool>,
+}
+
+fn main() {}
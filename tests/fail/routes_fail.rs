@@ -0,0 +1,14 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(routes = [r"^lanport_\d+$", r"^wanport_\d+$"])]
+    ports: (
+        std::collections::HashMap<String, bool>,
+        std::collections::HashMap<String, bool>,
+        std::collections::HashMap<String, bool>,
+    ),
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+use serde_flat_regex::flat_regex;
+
+mod ports {
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<std::collections::HashMap<String, bool>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        serde::Deserialize::deserialize(deserializer)
+    }
+}
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[serde(with = "ports")]
+    #[flat_regex(regex = r"^port_\d+$")]
+    ports: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
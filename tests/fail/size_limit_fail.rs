@@ -0,0 +1,10 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^port_\w{100}$", size_limit = 100)]
+    ports: std::collections::HashMap<String, bool>,
+}
+
+fn main() {}
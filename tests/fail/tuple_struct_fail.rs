@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(Debug, Deserialize)]
+struct Ports(#[flat_regex(regex = r"lanportstatus_\d+")] std::collections::HashMap<String, String>);
+
+fn main() {}
@@ -0,0 +1,12 @@
+use serde_flat_regex::flat_regex;
+
+struct Speed(u32);
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^lanportspeed_\d+$")]
+    speed: std::collections::HashMap<String, Speed>,
+}
+
+fn main() {}
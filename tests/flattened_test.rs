@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_flat_regex::{pattern, Flattened};
+
+pattern!(PortPat = r"^port_\d+$");
+pattern!(HostPat = r"^host_");
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Router {
+    id: u32,
+    #[serde(flatten)]
+    ports: Flattened<HashMap<String, bool>, PortPat>,
+    #[serde(flatten)]
+    hosts: Flattened<BTreeMap<String, String>, HostPat>,
+}
+
+#[test]
+fn flattened() {
+    let json = r#"{
+        "id": 1,
+        "port_0": true,
+        "port_1": false,
+        "portspeed": 100,
+        "host_a": "10.0.0.1"
+    }"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.id, 1);
+    assert_eq!(router.ports.len(), 2);
+    assert!(router.ports["port_0"]);
+    assert_eq!(router.hosts.into_inner()["host_a"], "10.0.0.1");
+}
+
+#[test]
+fn flattened_declaration_order() {
+    // unlike the attribute macro there is no ordering with the derive to get wrong
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[serde(flatten)]
+        ports: Flattened<Vec<(String, bool)>, PortPat>,
+    }
+
+    let ports: Ports = serde_json::from_str(r#"{"port_3": true, "other": 1}"#).unwrap();
+    assert_eq!(*ports.ports, vec![("port_3".to_string(), true)]);
+}
+
+#[test]
+fn flattened_serialize() {
+    let mut ports = Flattened::<BTreeMap<String, bool>, PortPat>::default();
+    ports.insert("port_0".to_string(), true);
+    let json = serde_json::to_value(&ports).unwrap();
+    assert_eq!(json, serde_json::json!({"port_0": true}));
+}
+
+#[test]
+fn deserialize_with() {
+    use serde_flat_regex::deserialize_flat_regex;
+
+    #[derive(Debug, Deserialize)]
+    struct Plain {
+        id: u32,
+        #[serde(
+            flatten,
+            deserialize_with = "deserialize_flat_regex::<HashMap<String, bool>, PortPat, _>"
+        )]
+        ports: HashMap<String, bool>,
+        #[serde(flatten, deserialize_with = "deserialize_flat_regex::<_, HostPat, _>")]
+        hosts: Vec<(String, String)>,
+    }
+
+    let json = r#"{"id": 1, "port_0": true, "portspeed": 100, "host_a": "10.0.0.1"}"#;
+    let plain: Plain = serde_json::from_str(json).unwrap();
+    assert_eq!(plain.id, 1);
+    assert_eq!(plain.ports, HashMap::from([("port_0".to_string(), true)]));
+    assert_eq!(
+        plain.hosts,
+        [("host_a".to_string(), "10.0.0.1".to_string())]
+    );
+}
+
+struct SpeedPat;
+
+impl serde_flat_regex::Pattern for SpeedPat {
+    fn pattern() -> &'static str {
+        r"^speed_\d+$"
+    }
+
+    fn regex() -> Result<&'static regex::Regex, regex::Error> {
+        static REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+        if let Some(regex) = REGEX.get() {
+            return Ok(regex);
+        }
+        let regex = regex::Regex::new(Self::pattern())?;
+        Ok(REGEX.get_or_init(|| regex))
+    }
+}
+
+#[test]
+fn regex_compiled_once() {
+    use serde_flat_regex::Pattern;
+
+    assert!(std::ptr::eq(
+        PortPat::regex().unwrap(),
+        PortPat::regex().unwrap()
+    ));
+    // a manual impl keeps the regex in its own static
+    assert!(std::ptr::eq(
+        SpeedPat::regex().unwrap(),
+        SpeedPat::regex().unwrap()
+    ));
+
+    let json = r#"[{"speed_0": 10, "x": 1}, {"speed_1": 100}]"#;
+    let speeds: Vec<Flattened<HashMap<String, u32>, SpeedPat>> =
+        serde_json::from_str(json).unwrap();
+    assert_eq!(speeds.len(), 2);
+    assert!(speeds.iter().all(|speeds| speeds.len() == 1));
+}
+
+#[test]
+fn deserialize_with_invalid_pattern() {
+    use serde_flat_regex::deserialize_flat_regex;
+
+    pattern!(BadPat = r"^port_(\d+$");
+
+    #[derive(Debug, Deserialize)]
+    struct Plain {
+        #[serde(flatten, deserialize_with = "deserialize_flat_regex::<_, BadPat, _>")]
+        _ports: HashMap<String, bool>,
+    }
+
+    for _ in 0..2 {
+        let err = serde_json::from_str::<Plain>(r#"{"port_0": true}"#).unwrap_err();
+        assert!(err.to_string().contains("unclosed group"));
+    }
+}
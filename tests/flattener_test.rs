@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::IntoDeserializer;
+use serde_flat_regex::{validate_pattern, RegexFlattener};
+
+fn from_json<C>(flattener: &RegexFlattener, json: &str) -> Result<C, serde_json::Error>
+where
+    C: serde_flat_regex::FlatCollection + Default + Extend<(C::Key, C::Value)>,
+    C::Key: for<'de> serde::Deserialize<'de> + AsRef<str>,
+    C::Value: for<'de> serde::Deserialize<'de>,
+{
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    flattener.deserialize_map(&mut deserializer)
+}
+
+#[test]
+fn pattern() {
+    let flattener = RegexFlattener::new().pattern(r"^port_\d+$");
+    let ports: HashMap<String, bool> = from_json(
+        &flattener,
+        r#"{"port_0": true, "port_1": false, "Port_2": true, "speed": 1}"#,
+    )
+    .unwrap();
+    assert_eq!(ports.len(), 2);
+    assert!(ports["port_0"]);
+    assert!(!ports["port_1"]);
+}
+
+#[test]
+fn reuses_compiled_regex() {
+    let flattener = RegexFlattener::new().pattern(r"^port_\d+$");
+    for json in [r#"{"port_0": true, "x": 1}"#, r#"{"port_1": false}"#] {
+        let ports: HashMap<String, bool> = from_json(&flattener, json).unwrap();
+        assert_eq!(ports.len(), 1);
+    }
+
+    // a clone keeps the compiled regex, changing the options compiles it again
+    let flattener = flattener.clone().case_insensitive(true);
+    let ports: HashMap<String, bool> = from_json(&flattener, r#"{"PORT_0": true}"#).unwrap();
+    assert_eq!(ports.len(), 1);
+    let flattener = flattener.pattern(r"^speed_\d+$");
+    let speeds: HashMap<String, u32> =
+        from_json(&flattener, r#"{"port_0": 1, "Speed_0": 100}"#).unwrap();
+    assert_eq!(speeds.len(), 1);
+}
+
+#[test]
+fn case_insensitive() {
+    let flattener = RegexFlattener::new()
+        .pattern(r"^port_\d+$")
+        .case_insensitive(true);
+    let ports: BTreeMap<String, bool> = from_json(
+        &flattener,
+        r#"{"port_0": true, "PORT_1": false, "speed": 1}"#,
+    )
+    .unwrap();
+    assert_eq!(ports.keys().collect::<Vec<_>>(), ["PORT_1", "port_0"]);
+}
+
+#[test]
+fn vec_keeps_order() {
+    let flattener = RegexFlattener::new().pattern(r"^port_");
+    let ports: Vec<(String, u16)> =
+        from_json(&flattener, r#"{"port_b": 2, "x": 0, "port_a": 1}"#).unwrap();
+    assert_eq!(
+        ports,
+        [("port_b".to_string(), 2), ("port_a".to_string(), 1)]
+    );
+}
+
+#[test]
+fn allow_null() {
+    let strict = RegexFlattener::new().pattern(r"^port_");
+    assert!(from_json::<HashMap<String, bool>>(&strict, "null").is_err());
+
+    let flattener = strict.allow_null(true);
+    let ports: HashMap<String, bool> = from_json(&flattener, "null").unwrap();
+    assert!(ports.is_empty());
+    let ports: HashMap<String, bool> = from_json(&flattener, r#"{"port_0": true}"#).unwrap();
+    assert_eq!(ports.len(), 1);
+}
+
+#[test]
+fn map_or_empty_seq() {
+    let strict = RegexFlattener::new().pattern(r"^port_");
+    assert!(from_json::<HashMap<String, bool>>(&strict, "[]").is_err());
+
+    let flattener = strict.map_or_empty_seq(true);
+    let ports: HashMap<String, bool> = from_json(&flattener, "[]").unwrap();
+    assert!(ports.is_empty());
+    assert!(from_json::<HashMap<String, bool>>(&flattener, "[1]").is_err());
+    assert!(from_json::<HashMap<String, bool>>(&flattener, "null").is_err());
+}
+
+#[test]
+fn unmatched_and_take() {
+    let flattener = RegexFlattener::new().pattern(r"^port_").unmatched(true);
+    let other: BTreeMap<String, u32> =
+        from_json(&flattener, r#"{"port_0": 1, "speed": 2, "mtu": 3}"#).unwrap();
+    assert_eq!(other.keys().collect::<Vec<_>>(), ["mtu", "speed"]);
+
+    let flattener = RegexFlattener::new().pattern(r"^port_").take(2);
+    let ports: Vec<(String, u32)> = from_json(
+        &flattener,
+        r#"{"port_0": 1, "x": 0, "port_1": 2, "port_2": 3}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        ports,
+        [("port_0".to_string(), 1), ("port_1".to_string(), 2)]
+    );
+}
+
+#[test]
+fn size_limit() {
+    let flattener = RegexFlattener::new()
+        .pattern(r"^port_\w{100}$")
+        .size_limit(100);
+    assert!(matches!(
+        flattener.build_regex(),
+        Err(regex::Error::CompiledTooBig(100))
+    ));
+    assert!(from_json::<HashMap<String, bool>>(&flattener, "{}").is_err());
+
+    let flattener = RegexFlattener::new()
+        .pattern(r"^port_\d+$")
+        .dfa_size_limit(1 << 16);
+    let ports: HashMap<String, bool> = from_json(&flattener, r#"{"port_0": true}"#).unwrap();
+    assert_eq!(ports.len(), 1);
+}
+
+#[test]
+fn errors() {
+    let err = from_json::<HashMap<String, bool>>(&RegexFlattener::new(), "{}").unwrap_err();
+    assert_eq!(err.to_string(), "RegexFlattener needs a pattern");
+
+    let invalid = RegexFlattener::new().pattern("port_(");
+    assert!(invalid.build_regex().is_err());
+    assert!(from_json::<HashMap<String, bool>>(&invalid, "{}").is_err());
+
+    let flattener = RegexFlattener::new().pattern("port_");
+    assert!(from_json::<HashMap<String, bool>>(&flattener, "[]").is_err());
+    assert!(from_json::<HashMap<String, bool>>(&flattener, r#"{"port_0": 1}"#).is_err());
+}
+
+#[test]
+fn other_formats() {
+    let bson = bson::bson!({"port_0": true, "speed": 100});
+    let ports: HashMap<String, bool> = RegexFlattener::new()
+        .pattern(r"^port_\d+$")
+        .deserialize_map(bson::Deserializer::new(bson))
+        .unwrap();
+    assert_eq!(ports.len(), 1);
+
+    let map: HashMap<String, u32> =
+        HashMap::from([("port_0".to_string(), 1), ("x".to_string(), 2)]);
+    let ports: HashMap<String, u32> = RegexFlattener::new()
+        .pattern(r"^port_\d+$")
+        .deserialize_map(map.into_deserializer())
+        .map_err(|e: serde::de::value::Error| e)
+        .unwrap();
+    assert_eq!(ports.len(), 1);
+}
+
+#[test]
+fn extend_into_merges_payloads() {
+    use serde::de::DeserializeSeed;
+
+    let flattener = RegexFlattener::new().pattern(r"^port_\d+$");
+    let mut ports: BTreeMap<String, bool> = BTreeMap::new();
+    for json in [
+        r#"{"port_0": true, "port_1": true, "speed": 1}"#,
+        r#"{"port_1": false, "port_2": true}"#,
+    ] {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        flattener
+            .extend_into(&mut ports)
+            .deserialize(&mut deserializer)
+            .unwrap();
+    }
+    assert_eq!(
+        ports,
+        BTreeMap::from([
+            ("port_0".to_string(), true),
+            ("port_1".to_string(), false),
+            ("port_2".to_string(), true),
+        ])
+    );
+}
+
+#[test]
+fn validate() {
+    assert!(validate_pattern(r"^port_\d+$").is_ok());
+    assert!(validate_pattern(r"(?i)^port_(?P<idx>\d+)$").is_ok());
+    assert!(validate_pattern("").is_ok());
+    assert!(matches!(
+        validate_pattern(r"^port_(\d+$"),
+        Err(regex::Error::Syntax(_))
+    ));
+    assert!(validate_pattern(r"^port_\p{Foo}$").is_err());
+    // the same error as the flattener reports
+    let pattern = format!("^{}_\\d+[", "port");
+    let err = validate_pattern(&pattern).unwrap_err();
+    let flattener = RegexFlattener::new().pattern(pattern);
+    assert_eq!(flattener.build_regex().unwrap_err(), err);
+}
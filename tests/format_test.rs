@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(Debug, Deserialize, Serialize)]
+struct Router {
+    id: u32,
+    #[flat_regex(regex = r"^lanportstatus_\d+$", serialize_matching)]
+    lanports: BTreeMap<String, String>,
+    #[flat_regex(regex = r"^limit_")]
+    limits: HashMap<String, toml::Value>,
+}
+
+#[test]
+fn toml() {
+    let raw = r#"
+        id = 1
+        lanportstatus_0 = "UP"
+        lanportstatus_1 = "DOWN"
+        lanportspeed = "100"
+        limit_rate = 10
+        limit_burst = 1.5
+
+        [wifi]
+        ssid = "home"
+    "#;
+    let router: Router = toml::from_str(raw).unwrap();
+    assert_eq!(router.id, 1);
+    assert_eq!(router.lanports.len(), 2);
+    assert_eq!(router.limits["limit_rate"], toml::Value::Integer(10));
+    assert_eq!(router.limits["limit_burst"], toml::Value::Float(1.5));
+
+    let serialized = toml::to_string(&router).unwrap();
+    let value: toml::Table = toml::from_str(&serialized).unwrap();
+    assert_eq!(value["lanportstatus_0"].as_str(), Some("UP"));
+    assert!(!value.contains_key("lanportspeed"));
+}
+
+#[test]
+fn toml_tables() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Devices {
+        #[flat_regex(regex = r"^device_\d+$")]
+        devices: BTreeMap<String, Device>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Device {
+        name: String,
+    }
+
+    let raw = r#"
+        [device_0]
+        name = "router"
+
+        [device_1]
+        name = "switch"
+
+        [other]
+        name = "ignored"
+    "#;
+    let devices: Devices = toml::from_str(raw).unwrap();
+    assert_eq!(devices.devices.len(), 2);
+    assert_eq!(devices.devices["device_1"].name, "switch");
+}
+
+#[flat_regex]
+#[derive(Debug, Deserialize, Serialize)]
+struct YamlRouter {
+    id: u32,
+    #[flat_regex(regex = r"^lanportstatus_\d+$", serialize_matching)]
+    lanports: BTreeMap<String, String>,
+    #[flat_regex(regex = r"^limit_")]
+    limits: HashMap<String, serde_yaml::Value>,
+}
+
+#[test]
+fn yaml() {
+    let raw = r#"
+        id: 1
+        lanportstatus_0: UP
+        lanportstatus_1: DOWN
+        lanportspeed: "100"
+        limit_rate: 10
+        limit_window:
+          seconds: 60
+        wifi:
+          ssid: home
+    "#;
+    let router: YamlRouter = serde_yaml::from_str(raw).unwrap();
+    assert_eq!(router.id, 1);
+    assert_eq!(router.lanports.len(), 2);
+    assert_eq!(router.limits["limit_rate"], serde_yaml::Value::from(10));
+    assert_eq!(router.limits["limit_window"]["seconds"], 60);
+
+    let serialized = serde_yaml::to_string(&router).unwrap();
+    let round_trip: YamlRouter = serde_yaml::from_str(&serialized).unwrap();
+    assert_eq!(round_trip.lanports, router.lanports);
+    assert!(!serialized.contains("lanportspeed"));
+}
+
+#[test]
+fn yaml_keys() {
+    // merge keys aren't expanded by serde_yaml, `<<` is a plain key which doesn't match
+    let raw = r"
+        id: 1
+        <<: {lanportstatus_9: UP}
+        lanportstatus_0: UP
+    ";
+    let router: YamlRouter = serde_yaml::from_str(raw).unwrap();
+    assert_eq!(
+        router.lanports.keys().collect::<Vec<_>>(),
+        ["lanportstatus_0"]
+    );
+
+    // non-string keys can't be buffered as strings for the flattened fields
+    let raw = r"
+        id: 1
+        ? [lanportstatus_0]
+        : UP
+    ";
+    assert!(serde_yaml::from_str::<YamlRouter>(raw).is_err());
+}
@@ -2,7 +2,7 @@ use std::ffi::CString;
 use std::{collections::HashMap, marker::PhantomData, str::Utf8Error};
 
 use bson::bson;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_flat_regex::flat_regex;
 
 #[test]
@@ -17,6 +17,7 @@ fn compile() {
         asd: PhantomData<&'c str>,
     }
 
+    #[allow(dead_code, clippy::ptr_arg)]
     fn as_str(s: &String) -> Result<&str, Utf8Error> {
         Ok(s)
     }
@@ -88,6 +89,75 @@ fn json() {
     assert_eq!(res.rest.len(), 4)
 }
 
+#[test]
+fn bson_with_plain_flatten() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize, PartialEq, Clone)]
+    struct Foo {
+        id: u32,
+        #[flat_regex(regex = r"lanport(status|speed)_\d+")]
+        rest: std::collections::HashMap<std::string::String, String>,
+        #[serde(flatten)]
+        tasdasd: HashMap<String, String>,
+    }
+
+    let bson = bson!({
+        "id": 123,
+        "lanportspeed_0": "100",
+        "lanportstatus_0": "UP",
+        "lanportspeed_1": "",
+        "lanportstatus_10": "DOWN",
+        "othterfield": "ASD",
+        "lanport": "ADDDD"
+    });
+
+    let res: Foo = bson::from_bson(bson.clone()).expect("from bson failed");
+    assert_eq!(res.tasdasd.len(), 6);
+    assert_eq!(res.rest.len(), 4);
+    assert_eq!(res.rest["lanportstatus_10"], "DOWN");
+
+    let document = bson.as_document().unwrap().clone();
+    let bytes = bson::to_vec(&document).unwrap();
+    let from_bytes: Foo = bson::from_slice(&bytes).expect("from slice failed");
+    assert_eq!(from_bytes, res);
+}
+
+#[test]
+fn json_rest() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Foo {
+        #[flat_regex(rest)]
+        other: HashMap<String, String>,
+        id: u32,
+        #[flat_regex(regex = r"lanportstatus_\d+")]
+        status: HashMap<String, String>,
+        #[flat_regex(regex = r"lanportspeed_\d+")]
+        speed: HashMap<String, String>,
+    }
+
+    let raw = r#"
+    {
+        "id": 123,
+        "lanportspeed_0": "100",
+        "lanportstatus_0": "UP",
+        "lanportstatus_10": "DOWN",
+        "othterfield": "ASD",
+        "lanport": "ADDDD"
+    }"#;
+
+    let res: Foo = serde_json::from_str(raw).expect("from str failed");
+
+    assert_eq!(res.id, 123);
+    assert_eq!(res.status.len(), 2);
+    assert_eq!(res.speed.len(), 1);
+    assert_eq!(res.other.len(), 2);
+    assert!(res.other.contains_key("othterfield") && res.other.contains_key("lanport"));
+    for key in res.other.keys() {
+        assert!(!res.status.contains_key(key) && !res.speed.contains_key(key));
+    }
+}
+
 #[test]
 fn json_should_fail() {
     #[flat_regex]
@@ -194,6 +264,34 @@ fn bson_test() {
     assert_eq!(router_status.lanports.len(), 3)
 }
 
+#[test]
+fn json_value() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Device {
+        id: u32,
+        #[flat_regex(regex = r"^attr_")]
+        attrs: HashMap<String, serde_json::Value>,
+    }
+
+    let json =
+        r#"{"id": 1, "attr_name": "router", "attr_ports": 4, "attr_wifi": true, "other": null}"#;
+    let device: Device = serde_json::from_str(json).unwrap();
+    assert_eq!(device.id, 1);
+    assert_eq!(device.attrs.len(), 3);
+    assert_eq!(device.attrs["attr_name"], serde_json::json!("router"));
+    assert_eq!(device.attrs["attr_ports"], serde_json::json!(4));
+    assert_eq!(device.attrs["attr_wifi"], serde_json::json!(true));
+
+    let bson =
+        bson!({"id": 1, "attr_name": "router", "attr_ports": 4, "attr_wifi": true, "other": "x"});
+    let device: Device = bson::from_bson(bson).unwrap();
+    assert_eq!(device.attrs.len(), 3);
+    assert_eq!(device.attrs["attr_name"], serde_json::json!("router"));
+    assert_eq!(device.attrs["attr_ports"], serde_json::json!(4));
+    assert_eq!(device.attrs["attr_wifi"], serde_json::json!(true));
+}
+
 #[test]
 fn enum_test() {
     #[flat_regex]
@@ -232,8 +330,2691 @@ fn enum_test() {
 }
 
 #[test]
-fn should_fail_at_compliltime() {
-    let t = trybuild::TestCases::new();
-    t.compile_fail("tests/fail/regex_fail.rs");
-    t.compile_fail("tests/fail/key_access_fail.rs");
+fn serialize_matching() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct RouterStatus {
+        id: u32,
+        #[flat_regex(regex = r"lanportstatus_\d+", serialize_matching)]
+        lanports: std::collections::BTreeMap<String, String>,
+    }
+
+    let router_status = RouterStatus {
+        id: 1,
+        lanports: std::collections::BTreeMap::from([
+            ("lanportstatus_0".to_string(), "UP".to_string()),
+            ("lanportstatus_1".to_string(), "DOWN".to_string()),
+            ("lanportspeed_0".to_string(), "100".to_string()),
+        ]),
+    };
+
+    let json = serde_json::to_value(&router_status).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "id": 1,
+            "lanportstatus_0": "UP",
+            "lanportstatus_1": "DOWN"
+        })
+    )
+}
+
+#[test]
+fn regex_expr() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        id: u32,
+        #[flat_regex(regex = concat!(env!("CARGO_PKG_NAME"), r"_port_\d+"))]
+        ports: HashMap<String, bool>,
+    }
+
+    let raw = r#"{
+        "id": 1,
+        "serde_flat_regex_port_0": true,
+        "serde_flat_regex_port_1": false,
+        "other_port_2": true
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+
+    assert_eq!(router_status.id, 1);
+    assert_eq!(
+        router_status.ports,
+        HashMap::from([
+            ("serde_flat_regex_port_0".to_string(), true),
+            ("serde_flat_regex_port_1".to_string(), false)
+        ])
+    )
+}
+
+#[test]
+fn regex_runtime_expr() {
+    fn tenant_pattern() -> &'static str {
+        static PATTERN: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+        PATTERN.get_or_init(|| format!(r"^{}_port_\d+$", "tenant"))
+    }
+
+    // a macro other than `concat!` and the like may expand to a runtime expression
+    macro_rules! tenant_pattern {
+        () => {
+            tenant_pattern()
+        };
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(regex = tenant_pattern())]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = tenant_pattern!())]
+        tenant_ports: HashMap<String, bool>,
+        #[flat_regex(rest)]
+        rest: HashMap<String, bool>,
+    }
+
+    let raw = r#"{"tenant_port_0": true, "other_port_1": false}"#;
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+    assert_eq!(router_status.ports.len(), 1);
+    assert_eq!(router_status.tenant_ports.len(), 1);
+    assert_eq!(router_status.rest.len(), 1);
+    // the expressions are only known at runtime, the rest field has no pattern
+    assert_eq!(
+        RouterStatus::FLAT_REGEX_PATTERNS,
+        &[("ports", None), ("tenant_ports", None), ("rest", None)]
+    );
+}
+
+#[test]
+fn array() {
+    #[allow(dead_code)]
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        id: u32,
+        #[flat_regex(regex = r"^port_(?P<idx>\d+)$", array, key_from_capture = "idx")]
+        ports: [bool; 4],
+    }
+
+    let raw = r#"{
+        "id": 1,
+        "port_2": true,
+        "port_0": true,
+        "port_1": false,
+        "port_3": false,
+        "port_speed": "100"
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+    assert_eq!(router_status.ports, [true, false, true, false]);
+
+    let missing = r#"{"id": 1, "port_0": true, "port_1": false, "port_3": false}"#;
+    assert!(serde_json::from_str::<RouterStatus>(missing).is_err());
+
+    let out_of_range = r#"{"id": 1, "port_0": true, "port_1": false, "port_2": true, "port_3": false, "port_4": true}"#;
+    assert!(serde_json::from_str::<RouterStatus>(out_of_range).is_err());
+}
+
+#[test]
+fn array_allow_missing() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(
+            regex = r"^port_(?P<idx>\d+)$",
+            array,
+            key_from_capture = "idx",
+            allow_missing
+        )]
+        ports: [Option<String>; 3],
+    }
+
+    let raw = r#"{"port_2": "UP", "port_0": "DOWN"}"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+    assert_eq!(
+        router_status.ports,
+        [Some("DOWN".to_string()), None, Some("UP".to_string())]
+    );
+}
+
+#[test]
+fn serde_default() {
+    #[flat_regex]
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    struct Ports {
+        #[serde(default)]
+        id: u32,
+        #[serde(default)]
+        #[flat_regex(regex = r"lanportstatus_\d+")]
+        lanports: HashMap<String, String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct RouterStatus {
+        wifi_status: bool,
+        #[serde(default)]
+        ports: Ports,
+        #[serde(default)]
+        optional_ports: Option<Ports>,
+    }
+
+    let ports: Ports = serde_json::from_str(r#"{"id": 1}"#).unwrap();
+    assert_eq!(
+        ports,
+        Ports {
+            id: 1,
+            lanports: HashMap::new()
+        }
+    );
+
+    let router_status: RouterStatus = serde_json::from_str(r#"{"wifi_status": true}"#).unwrap();
+    assert_eq!(
+        router_status,
+        RouterStatus {
+            wifi_status: true,
+            ports: Ports::default(),
+            optional_ports: None
+        }
+    );
+
+    let router_status: RouterStatus =
+        serde_json::from_str(r#"{"wifi_status": true, "optional_ports": {}}"#).unwrap();
+    assert_eq!(router_status.optional_ports, Some(Ports::default()));
+}
+
+#[test]
+fn patterns_const() {
+    #[allow(dead_code)]
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus<'a> {
+        id: u32,
+        #[flat_regex(regex = r"lanportstatus_\d+")]
+        lanport_status: HashMap<String, bool>,
+        #[serde(borrow)]
+        #[flat_regex(regex = concat!("wan", r"_\d+"))]
+        wan: HashMap<String, &'a str>,
+    }
+
+    assert_eq!(
+        RouterStatus::FLAT_REGEX_PATTERNS,
+        &[
+            ("lanport_status", Some(r"lanportstatus_\d+")),
+            ("wan", Some(r"wan_\d+"))
+        ]
+    );
+}
+
+#[cfg(feature = "aho-corasick")]
+#[test]
+fn literals() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        id: u32,
+        #[flat_regex(literals = [
+            "lanport_", "wanport_", "dslport_", "usbport_", "wifi24_", "wifi5_", "wifi6_",
+            "guest_", "mesh_", "vpn_", "dhcp_", "dns_", "ntp_", "upnp_", "nat_", "fw_",
+        ])]
+        ports: HashMap<String, bool>,
+    }
+
+    let raw = r#"{
+        "id": 1,
+        "lanport_0": true,
+        "wanport_0": false,
+        "wifi5_status": true,
+        "fw_enabled": true,
+        "uptime": 100,
+        "serial": "ABC"
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+
+    assert_eq!(router_status.id, 1);
+    assert_eq!(
+        router_status.ports,
+        HashMap::from([
+            ("lanport_0".to_string(), true),
+            ("wanport_0".to_string(), false),
+            ("wifi5_status".to_string(), true),
+            ("fw_enabled".to_string(), true),
+        ])
+    );
+}
+
+#[test]
+fn no_match_does_not_allocate() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(regex = r"lanportstatus_\d+")]
+        lanports: HashMap<String, String>,
+        #[flat_regex(regex = r"wanportstatus_\d+")]
+        wanports: HashMap<String, String>,
+        #[flat_regex(regex = r"dslportstatus_\d+")]
+        dslports: HashMap<String, String>,
+    }
+
+    let raw = r#"{
+        "lanportstatus_0": "UP",
+        "lanportspeed_0": "100",
+        "wifi_status": "UP"
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+
+    assert_eq!(router_status.lanports.len(), 1);
+    assert_eq!(router_status.wanports.capacity(), 0);
+    assert_eq!(router_status.dslports.capacity(), 0);
+}
+
+#[test]
+fn variant_level_field() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(tag = "type")]
+    enum Device {
+        #[flat_regex(field = "ports", regex = r"^lanport_\d+$")]
+        Router { ports: HashMap<String, bool> },
+        #[flat_regex(field = "ports", regex = r"^swport_\d+$")]
+        Switch {
+            id: u32,
+            ports: HashMap<String, bool>,
+        },
+    }
+
+    let json = r#"{"type": "Router", "lanport_0": true, "swport_0": false}"#;
+    let Device::Router { ports } = serde_json::from_str(json).unwrap() else {
+        panic!("expected a router")
+    };
+    assert_eq!(ports, HashMap::from([("lanport_0".to_string(), true)]));
+
+    let json = r#"{"type": "Switch", "id": 1, "lanport_0": true, "swport_0": false}"#;
+    let Device::Switch { id, ports } = serde_json::from_str(json).unwrap() else {
+        panic!("expected a switch")
+    };
+    assert_eq!(id, 1);
+    assert_eq!(ports, HashMap::from([("swport_0".to_string(), false)]));
+}
+
+#[test]
+fn item_level_field() {
+    #[flat_regex(field = "lanports", regex = r"lanportstatus_\d+")]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        id: u32,
+        lanports: HashMap<String, String>,
+    }
+
+    let raw = r#"{
+        "id": 1,
+        "lanportstatus_0": "UP",
+        "lanportstatus_1": "DOWN",
+        "lanportspeed": "100"
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+
+    assert_eq!(router_status.id, 1);
+    assert_eq!(
+        router_status.lanports,
+        HashMap::from([
+            ("lanportstatus_0".to_string(), "UP".to_string()),
+            ("lanportstatus_1".to_string(), "DOWN".to_string())
+        ])
+    );
+}
+
+#[test]
+fn value_variant() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum PortField {
+        #[serde(rename = "status")]
+        Status(String),
+        #[serde(rename = "speed")]
+        Speed(u32),
+        #[serde(rename = "up")]
+        Up,
+    }
+
+    fn choose_variant(key: &str) -> &'static str {
+        if key.ends_with("_status") {
+            "status"
+        } else if key.ends_with("_speed") {
+            "speed"
+        } else {
+            "up"
+        }
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        id: u32,
+        #[flat_regex(regex = r"^port_\d+_", value_variant = "choose_variant")]
+        ports: HashMap<String, PortField>,
+    }
+
+    let raw = r#"{
+        "id": 1,
+        "port_0_status": "UP",
+        "port_0_speed": 100,
+        "port_1_up": null,
+        "lanport": "ADDDD"
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+
+    assert_eq!(router_status.id, 1);
+    assert_eq!(
+        router_status.ports,
+        HashMap::from([
+            (
+                "port_0_status".to_string(),
+                PortField::Status("UP".to_string())
+            ),
+            ("port_0_speed".to_string(), PortField::Speed(100)),
+            ("port_1_up".to_string(), PortField::Up),
+        ])
+    );
+}
+
+#[test]
+fn reserve_hint() {
+    use serde::de::value::MapDeserializer;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ports(#[flat_regex(regex = r"^lanportstatus_\d+$", reserve_hint)] HashMap<String, bool>);
+
+    let input = (0..1000)
+        .map(|i| (format!("lanportstatus_{i}"), i % 2 == 0))
+        .chain((0..10).map(|i| (format!("lanportspeed_{i}"), true)))
+        .collect::<Vec<_>>();
+
+    // the first key matches, so the hint covers all entries
+    let deserializer: MapDeserializer<_, serde::de::value::Error> =
+        MapDeserializer::new(input.into_iter());
+    let ports = Ports::deserialize(deserializer).unwrap();
+
+    assert_eq!(ports.0.len(), 1000);
+    assert!(ports.0.capacity() >= 1010);
+}
+
+/// Subscriber recording the messages of the trace events.
+#[derive(Default, Clone)]
+struct TraceRecorder(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl tracing::Subscriber for TraceRecorder {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+        *metadata.level() == tracing::Level::TRACE
+    }
+
+    fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        tracing::span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        struct Message<'a>(&'a mut Vec<String>);
+
+        impl tracing::field::Visit for Message<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.push(format!("{value:?}"));
+                }
+            }
+        }
+
+        event.record(&mut Message(&mut self.0.lock().unwrap()));
+    }
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[test]
+fn trace_skipped() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        id: u32,
+        #[flat_regex(regex = r"lanportstatus_\d+", trace_skipped)]
+        lanports: HashMap<String, String>,
+    }
+
+    let raw = r#"{
+        "id": 1,
+        "lanportstatus_0": "UP",
+        "lanportspeed_0": "100"
+    }"#;
+
+    let recorder = TraceRecorder::default();
+    let router_status: RouterStatus =
+        tracing::subscriber::with_default(recorder.clone(), || serde_json::from_str(raw).unwrap());
+
+    assert_eq!(router_status.id, 1);
+    assert_eq!(
+        router_status.lanports,
+        HashMap::from([("lanportstatus_0".to_string(), "UP".to_string())])
+    );
+
+    // without the `tracing` feature the skipped keys aren't logged
+    let logs: &[&str] = if cfg!(feature = "tracing") {
+        &["skipped key `lanportspeed_0` not matching flat_regex field `lanports`"]
+    } else {
+        &[]
+    };
+    assert_eq!(*recorder.0.lock().unwrap(), logs);
+}
+
+fn strip_v1(key: &str) -> Result<&str, ()> {
+    key.strip_prefix("v1.").ok_or(())
+}
+
+fn strip_v2(key: &str) -> Result<&str, ()> {
+    key.strip_prefix("v2.").ok_or(())
+}
+
+#[test]
+fn key_access_fallbacks() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^port_\d+$", key_access = ["strip_v1", "strip_v2"])]
+        ports: HashMap<String, u16>,
+    }
+
+    let json = r#"{"v1.port_0": 80, "v2.port_1": 443, "port_2": 8080, "v1.host": 1}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+    assert_eq!(ports.ports["v1.port_0"], 80);
+    assert_eq!(ports.ports["v2.port_1"], 443);
+}
+
+fn id_as_string(key: u64) -> String {
+    format!("{:x}", key)
+}
+
+#[test]
+fn shared_str_keys() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^port_\d+$", serialize_matching)]
+        boxed: HashMap<Box<str>, bool>,
+        #[flat_regex(regex = r"^port_\d+$")]
+        rc: HashMap<Rc<str>, bool>,
+        #[flat_regex(regex = r"^port_\d+$", borrow_key)]
+        arc: HashMap<Arc<str>, bool>,
+    }
+
+    let json = r#"{"port_0": true, "port_1": false, "x": true}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.boxed.len(), 2);
+    assert!(ports.boxed["port_0"]);
+    assert_eq!(ports.rc.len(), 2);
+    assert!(!ports.rc["port_1"]);
+    assert_eq!(ports.arc.len(), 2);
+    assert_eq!(
+        serde_json::to_value(&ports.boxed).unwrap(),
+        serde_json::json!({"port_0": true, "port_1": false})
+    );
+
+    let bson = bson!({"port_0": true, "x": false});
+    let ports: Ports = bson::from_bson(bson).unwrap();
+    assert_eq!(ports.rc.keys().collect::<Vec<_>>(), [&Rc::from("port_0")]);
+}
+
+#[test]
+fn key_access_owned() {
+    // serde_json only parses integer keys outside of flatten
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Devices(
+        #[flat_regex(regex = r"^[a-f]+$", key_access_owned = "id_as_string")] HashMap<u64, bool>,
+    );
+
+    let json = r#"{"10": true, "11": false, "9": true, "255": true}"#;
+    let devices: Devices = serde_json::from_str(json).unwrap();
+    assert_eq!(devices.0.len(), 3);
+    assert!(devices.0[&10]);
+    assert!(!devices.0[&11]);
+    assert!(devices.0[&255]);
+}
+
+#[test]
+fn base_pattern() {
+    #[flat_regex(base = r"^device_\d+_")]
+    #[derive(Debug, Deserialize)]
+    struct Devices {
+        #[flat_regex(regex = r"status$")]
+        status: HashMap<String, bool>,
+        #[flat_regex(regex = r"speed$")]
+        speed: HashMap<String, u32>,
+    }
+
+    let json = r#"{
+        "device_0_status": true,
+        "device_1_status": false,
+        "device_0_speed": 100,
+        "router_status": true,
+        "device_x_speed": 10
+    }"#;
+    let devices: Devices = serde_json::from_str(json).unwrap();
+    assert_eq!(devices.status.len(), 2);
+    assert_eq!(devices.speed.len(), 1);
+    assert_eq!(devices.speed["device_0_speed"], 100);
+    assert_eq!(
+        Devices::FLAT_REGEX_PATTERNS,
+        &[
+            ("status", Some(r"^device_\d+_status$")),
+            ("speed", Some(r"^device_\d+_speed$"))
+        ]
+    );
+}
+
+#[test]
+fn allow_null() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ports(#[flat_regex(regex = r"^port_\d+$", allow_null)] HashMap<String, bool>);
+
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        ports: Ports,
+    }
+
+    let router: Router = serde_json::from_str(r#"{"ports": null}"#).unwrap();
+    assert!(router.ports.0.is_empty());
+    let router: Router = serde_json::from_str(r#"{"ports": {"port_0": true, "x": 1}}"#).unwrap();
+    assert_eq!(router.ports.0.len(), 1);
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    #[allow(dead_code)]
+    struct StrictPorts(#[flat_regex(regex = r"^port_\d+$")] HashMap<String, bool>);
+
+    assert!(serde_json::from_str::<StrictPorts>("null").is_err());
+}
+
+macro_rules! ports_struct {
+    ($name:ident) => {
+        #[flat_regex]
+        #[derive(Debug, Deserialize)]
+        pub struct $name {
+            #[flat_regex(regex = r"^port_\d+$")]
+            pub ports: HashMap<String, bool>,
+        }
+    };
+}
+
+#[test]
+fn helper_scope() {
+    mod nested {
+        use super::*;
+
+        ports_struct!(NestedPorts);
+    }
+
+    fn in_fn_body() -> usize {
+        ports_struct!(FnPorts);
+        let ports: FnPorts = serde_json::from_str(r#"{"port_0": true, "x": 1}"#).unwrap();
+        ports.ports.len()
+    }
+
+    let ports: nested::NestedPorts =
+        serde_json::from_str(r#"{"port_0": true, "port_1": true}"#).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+    assert_eq!(in_fn_body(), 1);
+}
+
+/// Accepts `port` with one typo, e.g. `prot` or `pory`.
+fn is_port(key: &str) -> bool {
+    key.len() == 4
+        && key
+            .chars()
+            .zip("port".chars())
+            .filter(|(a, b)| a != b)
+            .count()
+            <= 2
+}
+
+#[test]
+fn matcher() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        #[flat_regex(matcher = "is_port")]
+        ports: HashMap<String, u16>,
+        #[flat_regex(rest)]
+        other: HashMap<String, u16>,
+    }
+
+    let json = r#"{"port": 80, "prot": 81, "pory": 82, "name": 1, "ports": 2}"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.ports.len(), 3);
+    assert_eq!(router.other.len(), 2);
+    assert!(router.other.contains_key("name") && router.other.contains_key("ports"));
+}
+
+fn arc_mutex<T>(inner: T) -> std::sync::Arc<std::sync::Mutex<T>> {
+    std::sync::Arc::new(std::sync::Mutex::new(inner))
+}
+
+#[test]
+fn wrap() {
+    use std::sync::{Arc, Mutex};
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        #[flat_regex(regex = r"^port_\d+$", wrap = "Arc::new")]
+        ports: Arc<HashMap<String, bool>>,
+        #[flat_regex(regex = r"^host_", wrap = "arc_mutex")]
+        hosts: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    let json = r#"{"port_0": true, "port_1": false, "host_a": "10.0.0.1", "x": 1}"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.ports.len(), 2);
+    assert_eq!(router.hosts.lock().unwrap()["host_a"], "10.0.0.1");
+}
+
+#[test]
+fn collect_into() {
+    #[derive(Debug)]
+    struct PortMap(HashMap<String, bool>);
+
+    impl From<HashMap<String, bool>> for PortMap {
+        fn from(map: HashMap<String, bool>) -> Self {
+            PortMap(map)
+        }
+    }
+
+    /// A port map with at least one port up.
+    #[derive(Debug)]
+    struct UpPorts(HashMap<String, bool>);
+
+    impl TryFrom<HashMap<String, bool>> for UpPorts {
+        type Error = &'static str;
+
+        fn try_from(map: HashMap<String, bool>) -> Result<Self, Self::Error> {
+            if map.values().any(|up| *up) {
+                Ok(UpPorts(map))
+            } else {
+                Err("no port is up")
+            }
+        }
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        #[flat_regex(regex = r"^port_\d+$", collect_into = "HashMap<String, bool>")]
+        ports: PortMap,
+        #[flat_regex(regex = r"^lan_\d+$", try_collect_into = "HashMap<String, bool>")]
+        lans: UpPorts,
+    }
+
+    let json = r#"{"port_0": true, "port_1": false, "lan_0": false, "lan_1": true}"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.ports.0.len(), 2);
+    assert_eq!(router.lans.0.len(), 2);
+
+    let json = r#"{"port_0": true, "lan_0": false}"#;
+    let err = serde_json::from_str::<Router>(json).unwrap_err();
+    assert!(err.to_string().starts_with("no port is up"));
+}
+
+#[test]
+fn key_parse() {
+    use std::net::SocketAddr;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Peers {
+        #[flat_regex(regex = r"^10\.0\.0\.\d+:\d+$", key_parse, serialize_matching)]
+        peers: HashMap<SocketAddr, bool>,
+    }
+
+    let json = r#"{"10.0.0.1:80": true, "10.0.0.2:443": false, "localhost": true}"#;
+    let peers: Peers = serde_json::from_str(json).unwrap();
+    assert_eq!(peers.peers.len(), 2);
+    assert!(peers.peers[&"10.0.0.1:80".parse().unwrap()]);
+    assert_eq!(
+        serde_json::to_value(&peers).unwrap(),
+        serde_json::json!({"10.0.0.1:80": true, "10.0.0.2:443": false})
+    );
+
+    let err = serde_json::from_str::<Peers>(r#"{"10.0.0.1:99999": true}"#).unwrap_err();
+    assert!(err.to_string().contains("invalid socket address"));
+}
+
+#[test]
+fn replace() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanportstatus_(?P<idx>\d+)$", replace = "$idx")]
+        status: HashMap<String, String>,
+        #[flat_regex(regex = r"^lanportspeed_(\d+)$", replace = "port${1}", borrow_key)]
+        speed: HashMap<String, u32>,
+    }
+
+    let json =
+        r#"{"lanportstatus_0": "UP", "lanportstatus_12": "DOWN", "lanportspeed_0": 100, "x": 1}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.status["0"], "UP");
+    assert_eq!(ports.status["12"], "DOWN");
+    assert_eq!(ports.speed["port0"], 100);
+}
+
+#[test]
+fn map_or_empty_seq() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ports(#[flat_regex(regex = r"^port_\d+$", map_or_empty_seq)] HashMap<String, bool>);
+
+    let ports: Ports = serde_json::from_str("[]").unwrap();
+    assert!(ports.0.is_empty());
+    let ports: Ports = serde_json::from_str(r#"{"port_0": true, "x": 1}"#).unwrap();
+    assert_eq!(ports.0.len(), 1);
+    assert!(serde_json::from_str::<Ports>("[1]").is_err());
+    assert!(serde_json::from_str::<Ports>("null").is_err());
+}
+
+#[test]
+fn captures_field() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Ports {
+        #[flat_regex(
+            regex = r"^lanport(status|speed)_(\d+)$",
+            captures_field = "port_captures",
+            serialize_matching
+        )]
+        ports: HashMap<String, String>,
+        port_captures: HashMap<String, Vec<String>>,
+    }
+
+    let json = r#"{"lanportstatus_0": "UP", "lanportspeed_1": "100", "x": "y"}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+    assert_eq!(ports.port_captures.len(), 2);
+    assert_eq!(ports.port_captures["lanportstatus_0"], ["status", "0"]);
+    assert_eq!(ports.port_captures["lanportspeed_1"], ["speed", "1"]);
+    assert_eq!(
+        serde_json::to_value(&ports).unwrap(),
+        serde_json::json!({"lanportstatus_0": "UP", "lanportspeed_1": "100"})
+    );
+}
+
+#[test]
+fn overflow() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        id: u32,
+        #[flat_regex(regex = r"^port_\d+$", overflow = "extra")]
+        ports: HashMap<String, bool>,
+        extra: HashMap<String, serde_json::Value>,
+        #[flat_regex(regex = r"^speed_\d+$", unmatched)]
+        not_speeds: HashMap<String, serde_json::Value>,
+        #[flat_regex(regex = r"^wifi_\d+$")]
+        wifi: HashMap<String, bool>,
+    }
+
+    let json = r#"{"id": 1, "port_0": true, "speed_0": 100, "name": "r1", "wifi_0": true}"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.id, 1);
+    assert_eq!(router.ports, HashMap::from([("port_0".to_string(), true)]));
+    assert_eq!(router.wifi, HashMap::from([("wifi_0".to_string(), true)]));
+    // the named field `id` isn't passed to flattened fields, `wifi_0` is skipped by `ports` unlike with `rest`
+    assert_eq!(
+        router.extra,
+        HashMap::from([
+            ("speed_0".to_string(), serde_json::json!(100)),
+            ("name".to_string(), serde_json::json!("r1")),
+            ("wifi_0".to_string(), serde_json::json!(true))
+        ])
+    );
+    assert_eq!(
+        router.not_speeds,
+        HashMap::from([
+            ("port_0".to_string(), serde_json::json!(true)),
+            ("name".to_string(), serde_json::json!("r1")),
+            ("wifi_0".to_string(), serde_json::json!(true))
+        ])
+    );
+    assert_eq!(
+        Router::FLAT_REGEX_PATTERNS,
+        [
+            ("ports", Some(r"^port_\d+$")),
+            ("extra", None),
+            ("not_speeds", None),
+            ("wifi", Some(r"^wifi_\d+$"))
+        ]
+        .as_slice()
+    );
+}
+
+#[test]
+fn value_from_capture() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Settings {
+        #[flat_regex(
+            regex = r"^kv_(?P<k>[a-z]+)_(?P<v>\d+)$",
+            key_from_capture = "k",
+            value_from_capture = "v"
+        )]
+        values: HashMap<String, u32>,
+        #[flat_regex(
+            regex = r"^port_(?P<port>\d+)_(?P<up>true|false)$",
+            key_from_capture = "port",
+            value_from_capture = "up"
+        )]
+        ports: BTreeMap<u16, bool>,
+    }
+
+    let json = r#"{"kv_mtu_1500": null, "kv_vlan_7": {"ignored": true}, "port_80_true": 0, "port_443_false": 0}"#;
+    let settings: Settings = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        settings.values,
+        HashMap::from([("mtu".to_string(), 1500), ("vlan".to_string(), 7)])
+    );
+    assert_eq!(settings.ports, BTreeMap::from([(80, true), (443, false)]));
+}
+
+#[test]
+fn const_generics() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Router<const N: usize = 2> {
+        #[serde(with = "fixed")]
+        ids: [u8; N],
+        #[flat_regex(regex = r"^port_\d+$")]
+        ports: HashMap<String, bool>,
+    }
+
+    mod fixed {
+        pub fn deserialize<'de, D: serde::Deserializer<'de>, const N: usize>(
+            deserializer: D,
+        ) -> Result<[u8; N], D::Error> {
+            let ids = <Vec<u8> as serde::Deserialize>::deserialize(deserializer)?;
+            ids.try_into()
+                .map_err(|_| serde::de::Error::custom("wrong length"))
+        }
+    }
+
+    let json = r#"{"ids": [1, 2], "port_0": true, "x": 1}"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.ids, [1, 2]);
+    assert_eq!(router.ports.len(), 1);
+    assert_eq!(Router::<2>::FLAT_REGEX_PATTERNS.len(), 1);
+    let router: Router<3> = serde_json::from_str(r#"{"ids": [1, 2, 3]}"#).unwrap();
+    assert_eq!(router.ids.len(), 3);
+}
+
+#[test]
+fn invert() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Interfaces {
+        #[flat_regex(regex = r"^alias_\d+$", invert)]
+        aliases: HashMap<String, String>,
+        #[flat_regex(regex = r"^port_\d+$", invert, reserve_hint)]
+        ports: HashMap<u16, String>,
+    }
+
+    let json = r#"{"alias_0": "eth0", "alias_1": "wlan0", "port_0": 80, "x": "y"}"#;
+    let interfaces: Interfaces = serde_json::from_str(json).unwrap();
+    assert_eq!(interfaces.aliases.len(), 2);
+    assert_eq!(interfaces.aliases["eth0"], "alias_0");
+    assert_eq!(interfaces.aliases["wlan0"], "alias_1");
+    assert_eq!(interfaces.ports[&80], "port_0");
+}
+
+#[test]
+fn into_indexed_vec() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(
+            regex = r"^port_(?P<idx>\d+)$",
+            into_indexed_vec,
+            key_from_capture = "idx"
+        )]
+        ports: Vec<u16>,
+    }
+
+    let json = r#"{"port_3": 443, "port_0": 80, "port_1": 8080, "portspeed": 100}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports, [80, 8080, 0, 443]);
+
+    let ports: Ports = serde_json::from_str("{}").unwrap();
+    assert!(ports.ports.is_empty());
+}
+
+#[test]
+fn into_indexed_vec_bounds() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(
+            regex = r"^port_(?P<idx>\d+)$",
+            into_indexed_vec,
+            key_from_capture = "idx"
+        )]
+        ports: Vec<u16>,
+        #[flat_regex(
+            regex = r"^mtu_(?P<idx>\d+)$",
+            into_indexed_vec,
+            key_from_capture = "idx",
+            max_index = 7
+        )]
+        mtus: Vec<u16>,
+    }
+
+    // `idx + 1` would overflow
+    let json = format!(r#"{{"port_{}": 80}}"#, usize::MAX);
+    let err = serde_json::from_str::<Ports>(&json).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("exceeds the maximum index 524287 of `ports`"));
+
+    // a single key can't allocate gigabytes, at most 1MiB of `u16`
+    let err = serde_json::from_str::<Ports>(r#"{"port_4000000000": 80}"#).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("index 4000000000 of key `port_4000000000` exceeds the maximum index 524287"));
+    let ports: Ports = serde_json::from_str(r#"{"port_524287": 80}"#).unwrap();
+    assert_eq!(ports.ports.len(), 524288);
+
+    let ports: Ports = serde_json::from_str(r#"{"mtu_7": 1500}"#).unwrap();
+    assert_eq!(ports.mtus.len(), 8);
+    assert!(serde_json::from_str::<Ports>(r#"{"mtu_8": 1500}"#).is_err());
+}
+
+#[test]
+fn contiguous() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^port_(?P<idx>\d+)$", contiguous, key_from_capture = "idx")]
+        ports: HashMap<String, u16>,
+        #[flat_regex(
+            regex = r"^speed_(?P<idx>\d+)$",
+            into_indexed_vec,
+            contiguous,
+            key_from_capture = "idx"
+        )]
+        speeds: Vec<u16>,
+    }
+
+    let json = r#"{"port_1": 443, "port_0": 80, "speed_0": 100, "speed_1": 1000}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+    assert_eq!(ports.speeds, [100, 1000]);
+
+    let json = r#"{"port_2": 443, "port_0": 80}"#;
+    let err = serde_json::from_str::<Ports>(json).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("missing key for index 1 of `ports`"));
+
+    let json = r#"{"speed_1": 1000}"#;
+    let err = serde_json::from_str::<Ports>(json).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("missing key for index 0 of `speeds`"));
+}
+
+#[test]
+fn contiguous_bounds() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^port_(?P<idx>\d+)$", contiguous, key_from_capture = "idx")]
+        ports: HashMap<String, u16>,
+        #[flat_regex(
+            regex = r"^speed_(?P<idx>\d+)$",
+            contiguous,
+            key_from_capture = "idx",
+            max_index = 3
+        )]
+        speeds: HashMap<String, u16>,
+    }
+
+    // `idx + 1` would overflow
+    let json = format!(r#"{{"port_{}": 80}}"#, usize::MAX);
+    let err = serde_json::from_str::<Ports>(&json).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("exceeds the maximum index 1048575 of `ports`"));
+
+    // the seen indices are limited to 1MiB
+    let err = serde_json::from_str::<Ports>(r#"{"port_4000000000": 80}"#).unwrap_err();
+    assert!(err.to_string().starts_with(
+        "index 4000000000 of key `port_4000000000` exceeds the maximum index 1048575"
+    ));
+
+    let err = serde_json::from_str::<Ports>(r#"{"speed_4": 100}"#).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("index 4 of key `speed_4` exceeds the maximum index 3 of `speeds`"));
+    let ports: Ports = serde_json::from_str(r#"{"port_0": 80, "speed_0": 100}"#).unwrap();
+    assert_eq!((ports.ports.len(), ports.speeds.len()), (1, 1));
+}
+
+#[test]
+fn generic_enum_lifetime() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(tag = "type")]
+    enum Device<'a, T> {
+        Router {
+            #[serde(borrow)]
+            #[flat_regex(regex = r"^port_\d+$")]
+            ports: HashMap<&'a str, bool>,
+            extra: T,
+        },
+        Switch {
+            id: u32,
+        },
+    }
+
+    let json = r#"{"type": "Router", "extra": 7, "port_0": true, "port_1": false, "name": "x"}"#;
+    let device: Device<u32> = serde_json::from_str(json).unwrap();
+    match device {
+        Device::Router { ports, extra } => {
+            assert_eq!(ports.len(), 2);
+            assert!(ports["port_0"]);
+            assert_eq!(extra, 7);
+        }
+        Device::Switch { .. } => panic!("expected a router"),
+    }
+    let device: Device<u32> = serde_json::from_str(r#"{"type": "Switch", "id": 1}"#).unwrap();
+    assert!(matches!(device, Device::Switch { id: 1 }));
+}
+
+#[test]
+fn value_into() {
+    use std::sync::Arc;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanportstatus_\d+$", value_into)]
+        status: HashMap<String, Arc<str>>,
+        #[flat_regex(regex = r"^lanportname_\d+$", value_into)]
+        names: HashMap<String, Box<str>>,
+    }
+
+    let json = r#"{"lanportstatus_0": "UP", "lanportname_0": "eth0", "x": 1}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(&*ports.status["lanportstatus_0"], "UP");
+    assert_eq!(&*ports.names["lanportname_0"], "eth0");
+}
+
+#[test]
+fn on_error_skip() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        id: u32,
+        #[flat_regex(regex = r"^lanportspeed_\d+$", on_error = "skip")]
+        speed: HashMap<String, u32>,
+        #[flat_regex(regex = r"^lanportname_\d+$", on_error = "skip", value_into)]
+        names: HashMap<String, Box<str>>,
+    }
+
+    let json = r#"{
+        "id": 1,
+        "lanportspeed_0": 100,
+        "lanportspeed_1": "fast",
+        "lanportspeed_2": 10,
+        "lanportname_0": "eth0",
+        "lanportname_1": {"name": "eth1"}
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.id, 1);
+    assert_eq!(
+        ports.speed,
+        HashMap::from([
+            ("lanportspeed_0".to_string(), 100),
+            ("lanportspeed_2".to_string(), 10)
+        ])
+    );
+    assert_eq!(ports.names.len(), 1);
+    assert_eq!(&*ports.names["lanportname_0"], "eth0");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn into_flat() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        online: bool,
+        #[flat_regex(regex = r"^lanportstatus_\d+$", into_flat)]
+        lanport_status: BTreeMap<String, bool>,
+    }
+
+    let json = serde_json::json!({"online": true, "lanportstatus_0": true, "lanportspeed_0": 100});
+    let mut status: RouterStatus = serde_json::from_value(json).unwrap();
+    assert!(status.online);
+    status
+        .lanport_status
+        .insert("lanportstatus_1".to_string(), false);
+
+    let flat = status.lanport_status_into_flat();
+    assert_eq!(
+        serde_json::Value::Object(flat.clone()),
+        serde_json::json!({"lanportstatus_0": true, "lanportstatus_1": false})
+    );
+    let mut object = flat;
+    object.insert("online".to_string(), serde_json::Value::Bool(false));
+    let again: RouterStatus = serde_json::from_value(serde_json::Value::Object(object)).unwrap();
+    assert_eq!(again.lanport_status, status.lanport_status);
+}
+
+mod keys {
+    pub mod c_string {
+        use std::ffi::CString;
+        use std::str::Utf8Error;
+
+        pub fn to_str(key: &CString) -> Result<&str, Utf8Error> {
+            key.to_str()
+        }
+    }
+}
+
+trait KeyStr {
+    fn key_str(&self) -> Result<&str, std::str::Utf8Error>;
+}
+
+impl KeyStr for std::ffi::CString {
+    fn key_str(&self) -> Result<&str, std::str::Utf8Error> {
+        self.to_str()
+    }
+}
+
+#[test]
+fn key_access_qualified_paths() {
+    use std::collections::BTreeMap;
+    use std::ffi::CString;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanportstatus_\d+$", key_access = "keys::c_string::to_str")]
+        status: BTreeMap<CString, bool>,
+        #[flat_regex(
+            regex = r"^lanportspeed_\d+$",
+            key_access = "<CString as KeyStr>::key_str"
+        )]
+        speed: BTreeMap<CString, u32>,
+    }
+
+    let json = serde_json::json!({"lanportstatus_0": true, "lanportspeed_0": 100, "x": 1});
+    let ports: Ports = serde_json::from_value(json).unwrap();
+    assert_eq!(ports.status.len(), 1);
+    assert_eq!(ports.speed[&CString::new("lanportspeed_0").unwrap()], 100);
+}
+
+#[test]
+fn prefix_suffix() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Devices {
+        #[flat_regex(prefix = "dev_", regex = r"port_\d+", suffix = "_up")]
+        up: HashMap<String, bool>,
+        #[flat_regex(prefix = "dev_", regex = r"port_\d+")]
+        ports: HashMap<String, u32>,
+    }
+
+    let json = r#"{
+        "dev_port_0_up": true,
+        "dev_port_1_up": false,
+        "dev_port_1": 7,
+        "dev_xport_2_up": true,
+        "port_3_up": true
+    }"#;
+    let devices: Devices = serde_json::from_str(json).unwrap();
+    assert_eq!(devices.up.len(), 2);
+    assert!(devices.up["dev_port_0_up"]);
+    assert_eq!(
+        devices.ports,
+        HashMap::from([("dev_port_1".to_string(), 7)])
+    );
+    assert_eq!(
+        Devices::FLAT_REGEX_PATTERNS,
+        &[
+            ("up", Some(r"^dev_(?:port_\d+)_up$")),
+            ("ports", Some(r"^dev_(?:port_\d+)$"))
+        ]
+    );
+}
+
+#[test]
+fn multimap() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(
+            regex = r"^port_(?P<idx>\d+)_addr_\d+$",
+            replace = "$idx",
+            multimap,
+            reserve_hint
+        )]
+        addrs: HashMap<String, Vec<String>>,
+    }
+
+    let json = r#"{
+        "port_0_addr_0": "10.0.0.1",
+        "port_0_addr_1": "10.0.0.2",
+        "port_1_addr_0": "10.0.1.1",
+        "port_1_name": "wan"
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        ports.addrs,
+        HashMap::from([
+            (
+                "0".to_string(),
+                vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]
+            ),
+            ("1".to_string(), vec!["10.0.1.1".to_string()]),
+        ])
+    );
+}
+
+#[test]
+fn integer_keys() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Vlans {
+        name: String,
+        #[flat_regex(regex = r"^\d{3}$", serialize_matching)]
+        vlans: BTreeMap<u16, String>,
+    }
+
+    let bson = bson!({
+        "name": "core",
+        "100": "mgmt",
+        "200": "guests",
+        "7": "short",
+    });
+    let vlans: Vlans = bson::from_bson(bson).unwrap();
+    assert_eq!(vlans.name, "core");
+    assert_eq!(
+        vlans.vlans,
+        BTreeMap::from([(100, "mgmt".to_string()), (200, "guests".to_string())])
+    );
+    assert_eq!(
+        serde_json::to_value(&vlans).unwrap(),
+        serde_json::json!({"name": "core", "100": "mgmt", "200": "guests"})
+    );
+
+    // formats with integer keys in the data model
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ids(#[flat_regex(regex = r"^1\d*$")] BTreeMap<u64, bool>);
+
+    let ids: Ids = serde::Deserialize::deserialize(serde::de::value::MapDeserializer::<
+        _,
+        serde::de::value::Error,
+    >::new(
+        vec![(1u64, true), (12, false), (2, true)].into_iter(),
+    ))
+    .unwrap();
+    assert_eq!(ids.0, BTreeMap::from([(1, true), (12, false)]));
+}
+
+#[test]
+fn take() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Sample {
+        #[flat_regex(regex = r"^sensor_\d+$", take = 2)]
+        sensors: HashMap<String, f64>,
+        total: u32,
+    }
+
+    let json = r#"{
+        "sensor_0": 1.0,
+        "sensor_1": 2.0,
+        "sensor_2": 3.0,
+        "sensor_3": 4.0,
+        "total": 4
+    }"#;
+    let sample: Sample = serde_json::from_str(json).unwrap();
+    assert_eq!(sample.sensors.len(), 2);
+    assert_eq!(sample.total, 4);
+}
+
+#[test]
+fn map_of_maps() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports<'a> {
+        #[flat_regex(regex = r"^lanport_\d+$")]
+        ports: HashMap<String, HashMap<String, bool>>,
+        #[flat_regex(regex = r"^wanport_\d+$")]
+        #[serde(borrow)]
+        wan: HashMap<&'a str, HashMap<&'a str, bool>>,
+        #[flat_regex(regex = r"^wifi_\d+$")]
+        #[serde(borrow)]
+        wifi: HashMap<String, HashMap<&'a str, bool>>,
+    }
+
+    let json = r#"{
+        "lanport_0": {"up": true, "poe": false},
+        "lanport_1": {"up": false},
+        "wanport_0": {"up": true},
+        "wifi_0": {"up": true},
+        "wifi": {"up": true}
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+    assert_eq!(
+        ports.ports["lanport_0"],
+        HashMap::from([("up".to_string(), true), ("poe".to_string(), false)])
+    );
+    assert_eq!(ports.wan["wanport_0"], HashMap::from([("up", true)]));
+    assert_eq!(ports.wifi["wifi_0"], HashMap::from([("up", true)]));
+}
+
+#[test]
+fn inline_flags() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Flags {
+        #[flat_regex(regex = r"(?i)^lanportstatus_\d+$")]
+        status: HashMap<String, bool>,
+        #[flat_regex(regex = r"(?s)^note_.+$")]
+        notes: HashMap<String, String>,
+        #[flat_regex(regex = r"(?x) ^ speed _ \d+ $  # the port speed
+        ")]
+        speed: HashMap<String, u32>,
+        #[flat_regex(prefix = "dev_", regex = r"(?i)port_\d+", suffix = "_up")]
+        up: HashMap<String, bool>,
+        #[flat_regex(
+            prefix = "dev_",
+            regex = r"(?x) port _ \d+  # the port
+        ",
+            suffix = "_down"
+        )]
+        down: HashMap<String, bool>,
+    }
+
+    let json = r#"{
+        "LANPORTSTATUS_0": true,
+        "lanportstatus_1": false,
+        "note_a\nb": "multiline key",
+        "speed_0": 100,
+        "speed 1": 10,
+        "dev_PORT_0_up": true,
+        "dev_port_1_up": true,
+        "DEV_port_2_up": true,
+        "dev_port_0_down": true,
+        "dev_port_1_down_x": true
+    }"#;
+    let flags: Flags = serde_json::from_str(json).unwrap();
+    assert_eq!(flags.status.len(), 2);
+    assert_eq!(flags.notes.len(), 1);
+    assert_eq!(flags.speed, HashMap::from([("speed_0".to_string(), 100)]));
+    assert_eq!(flags.up.len(), 2);
+    assert!(!flags.up.contains_key("DEV_port_2_up"));
+    assert_eq!(flags.down.len(), 1);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn gen_try_from_value() {
+    #[flat_regex(gen_try_from_value)]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        online: bool,
+        #[flat_regex(regex = r"^lanportstatus_\d+$")]
+        lanport_status: HashMap<String, bool>,
+    }
+
+    let json = serde_json::json!({"online": true, "lanportstatus_0": true, "wifistatus": false});
+    let status = RouterStatus::try_from(json).unwrap();
+    assert!(status.online);
+    assert_eq!(status.lanport_status.len(), 1);
+    assert!(RouterStatus::try_from(serde_json::json!({"online": 1})).is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn gen_merge_from_value() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex(gen_merge_from_value)]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        online: bool,
+        #[flat_regex(regex = r"^lanportstatus_\d+$")]
+        lanport_status: HashMap<String, bool>,
+        #[flat_regex(regex = r"^wanport_\d+$")]
+        wanports: BTreeMap<String, u32>,
+    }
+
+    let json = serde_json::json!({"online": true, "lanportstatus_0": true, "wanport_0": 1});
+    let mut status: RouterStatus = serde_json::from_value(json).unwrap();
+    let patch =
+        serde_json::json!({"online": false, "lanportstatus_1": false, "wanport_0": 2, "x": 1});
+    status.merge_from_value(patch).unwrap();
+    // other fields are left as they are
+    assert!(status.online);
+    assert_eq!(
+        status.lanport_status,
+        HashMap::from([
+            ("lanportstatus_0".to_string(), true),
+            ("lanportstatus_1".to_string(), false)
+        ])
+    );
+    assert_eq!(status.wanports["wanport_0"], 2);
+    assert!(status
+        .merge_from_value(serde_json::json!({"lanportstatus_2": 1}))
+        .is_err());
+
+    // compiles without the feature of its only field
+    #[flat_regex(gen_merge_from_value)]
+    #[derive(Debug, Default, Deserialize)]
+    struct Gated {
+        #[flat_regex(regex = r"^lanportstatus_\d+$", cfg_feature = "tracing")]
+        lanport_status: HashMap<String, bool>,
+    }
+
+    let mut gated = Gated::default();
+    gated
+        .merge_from_value(serde_json::json!({"lanportstatus_0": true}))
+        .unwrap();
+    assert_eq!(
+        gated.lanport_status.len(),
+        usize::from(cfg!(feature = "tracing"))
+    );
+}
+
+#[test]
+fn ascii_case_insensitive() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanport_[a-z]+_\d+$", ascii_case_insensitive)]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = r"^straße_ä$", ascii_case_insensitive)]
+        streets: HashMap<String, bool>,
+    }
+
+    let json = r#"{
+        "LanPort_Status_0": true,
+        "lanport_speed_1": false,
+        "STRASSE_Ä": true,
+        "STRAßE_ä": true,
+        "straße_Ä": true
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+    // only ASCII letters are folded, `ß` isn't `SS` and `Ä` isn't `ä`
+    assert_eq!(ports.streets.keys().collect::<Vec<_>>(), ["STRAßE_ä"]);
+    assert_eq!(
+        Ports::FLAT_REGEX_PATTERNS[0],
+        ("ports", Some(r"(?i-u:^lanport_[a-z]+_\d+$)"))
+    );
+}
+
+#[test]
+fn ci_literals() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanport_[a-z]+_\d+$", ci_literals)]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = r"^(?:wan|dsl)(_[a-z])+\.é$", ci_literals)]
+        uplinks: HashMap<String, bool>,
+    }
+
+    let json = r#"{
+        "LanPort_status_0": true,
+        "LANPORT_Status_1": false,
+        "WAN_a_b.é": true,
+        "Dsl_a.é": true,
+        "wan_A.é": false,
+        "wan_a.É": false
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    // the class `[a-z]` stays exact
+    assert_eq!(ports.ports.keys().collect::<Vec<_>>(), ["LanPort_status_0"]);
+    let mut uplinks = ports.uplinks.keys().collect::<Vec<_>>();
+    uplinks.sort();
+    assert_eq!(uplinks, ["Dsl_a.é", "WAN_a_b.é"]);
+    assert_eq!(
+        Ports::FLAT_REGEX_PATTERNS,
+        [
+            ("ports", Some(r"^(?i-u:lanport_)[a-z]+_\d+$")),
+            ("uplinks", Some(r"^(?:(?i-u:wan)|(?i-u:dsl))(_[a-z])+\.é$")),
+        ]
+    );
+}
+
+#[test]
+fn word_boundary() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = "port", word_boundary)]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = "(?i)lan", word_boundary, ascii_case_insensitive)]
+        lans: HashMap<String, bool>,
+    }
+
+    let json = r#"{
+        "port_0": true,
+        "wan.port": true,
+        "export_0": false,
+        "ports_1": false,
+        "LAN_1": true,
+        "vlan_1": false
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    let mut keys = ports.ports.keys().collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, ["port_0", "wan.port"]);
+    assert_eq!(ports.lans.keys().collect::<Vec<_>>(), ["LAN_1"]);
+}
+
+#[cfg(feature = "urldecode")]
+#[test]
+fn urldecode() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Form {
+        #[flat_regex(regex = r"^port\[\d+\]$", urldecode)]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = r"^host name$", urldecode, keep_encoded_key)]
+        hosts: HashMap<String, String>,
+        #[flat_regex(rest)]
+        rest: HashMap<String, serde_json::Value>,
+    }
+
+    let json = r#"{
+        "port%5B0%5D": true,
+        "port[1]": false,
+        "port%5Bx%5D": true,
+        "host%20name": "router"
+    }"#;
+    let form: Form = serde_json::from_str(json).unwrap();
+    let mut ports = form.ports.keys().collect::<Vec<_>>();
+    ports.sort();
+    assert_eq!(ports, ["port[0]", "port[1]"]);
+    assert_eq!(form.hosts["host%20name"], "router");
+    // `rest` excludes the keys the siblings take after decoding them
+    assert_eq!(form.rest.keys().collect::<Vec<_>>(), ["port%5Bx%5D"]);
+}
+
+#[test]
+fn on_collision_error() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(
+            regex = r"^(?:lan|wan)port_(?P<idx>\d+)$",
+            replace = "$idx",
+            on_collision = "error"
+        )]
+        ports: HashMap<String, bool>,
+    }
+
+    let ports: Ports = serde_json::from_str(r#"{"lanport_0": true, "wanport_1": false}"#).unwrap();
+    assert_eq!(ports.ports.len(), 2);
+
+    let err =
+        serde_json::from_str::<Ports>(r#"{"lanport_0": true, "wanport_0": false}"#).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("key `wanport_0` of `ports` collides with an earlier key"),
+        "{err}"
+    );
+}
+
+#[test]
+fn dedup_key() {
+    fn normalize(key: &str) -> String {
+        key.trim().to_lowercase()
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"(?i)^\s*port_\d+\s*$", dedup_key = "normalize")]
+        ports: HashMap<String, bool>,
+    }
+
+    let json = r#"{"Port_0": true, "port_0": false, " PORT_0 ": false, "port_1": false}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    // the first key wins and keeps its raw form
+    assert_eq!(
+        ports.ports,
+        HashMap::from([("Port_0".to_string(), true), ("port_1".to_string(), false)])
+    );
+}
+
+#[test]
+fn hasher_parameter() {
+    use std::collections::hash_map::{DefaultHasher, RandomState};
+    use std::hash::BuildHasherDefault;
+
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanport_\d+$", serialize_matching)]
+        default: HashMap<String, bool>,
+        #[flat_regex(regex = r"^wanport_\d+$", serialize_matching)]
+        random: HashMap<String, bool, RandomState>,
+        #[flat_regex(regex = r"^wifi_\d+$", reserve_hint)]
+        fixed: HashMap<String, bool, FixedState>,
+    }
+
+    let json =
+        serde_json::json!({"lanport_0": true, "wanport_0": false, "wifi_0": true, "wifi_1": false});
+    let ports: Ports = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(ports.default.len(), 1);
+    assert_eq!(ports.random.len(), 1);
+    assert_eq!(
+        ports.fixed,
+        HashMap::<_, _, FixedState>::from_iter([
+            ("wifi_0".to_string(), true),
+            ("wifi_1".to_string(), false)
+        ])
+    );
+    assert_eq!(serde_json::to_value(&ports).unwrap(), json);
+}
+
+/// The same struct with its fields in different orders, the helper of `status` keeps its name.
+mod field_order_a {
+    use serde::Deserialize;
+    use serde_flat_regex::flat_regex;
+    use std::collections::HashMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    pub struct Ports {
+        #[flat_regex(regex = r"^wanport_\d+$")]
+        pub wan: HashMap<String, bool>,
+        #[flat_regex(regex = r"^lanport_\d+$")]
+        pub status: HashMap<String, bool>,
+    }
+
+    pub fn status(json: serde_json::Value) -> HashMap<String, bool> {
+        __with_regex_Ports_status_9697a4c3(json).unwrap()
+    }
+}
+
+mod field_order_b {
+    use serde::Deserialize;
+    use serde_flat_regex::flat_regex;
+    use std::collections::HashMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    pub struct Ports {
+        #[flat_regex(regex = r"^lanport_\d+$")]
+        pub status: HashMap<String, bool>,
+        #[flat_regex(regex = r"^wanport_\d+$")]
+        pub wan: HashMap<String, bool>,
+    }
+
+    pub fn status(json: serde_json::Value) -> HashMap<String, bool> {
+        __with_regex_Ports_status_9697a4c3(json).unwrap()
+    }
+}
+
+#[test]
+fn helper_name_independent_of_field_order() {
+    let json = serde_json::json!({"lanport_0": true, "wanport_0": false});
+    assert_eq!(
+        field_order_a::status(json.clone()),
+        field_order_b::status(json.clone())
+    );
+    let a: field_order_a::Ports = serde_json::from_value(json.clone()).unwrap();
+    let b: field_order_b::Ports = serde_json::from_value(json).unwrap();
+    assert_eq!((a.status, a.wan), (b.status, b.wan));
+}
+
+mod forbid_unsafe {
+    // the generated helpers contain no `unsafe`, with any feature
+    #![forbid(unsafe_code)]
+
+    use serde::{Deserialize, Serialize};
+    use serde_flat_regex::flat_regex;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[flat_regex]
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Router {
+        #[flat_regex(regex = r"^lanport_\d+$", serialize_matching)]
+        pub lan: BTreeMap<String, bool>,
+        #[flat_regex(regex = r"^led_(?P<idx>\d+)$", array, key_from_capture = "idx")]
+        pub leds: [bool; 2],
+        #[flat_regex(
+            regex = r"^mtu_(?P<idx>\d+)$",
+            into_indexed_vec,
+            key_from_capture = "idx"
+        )]
+        pub mtu: Vec<u32>,
+        #[flat_regex(regex = r"^speed_\d+$", on_error = "skip", borrow_key)]
+        pub speeds: HashMap<String, u32>,
+        #[flat_regex(regex = r"^wan_\d+$", empty_is_none)]
+        pub wan: Option<HashMap<String, bool>>,
+        #[flat_regex(routes = [r"^vlan_\d+$", r"^vrf_\d+$"])]
+        pub routes: (HashMap<String, u16>, HashMap<String, u16>),
+        #[flat_regex(rest)]
+        pub rest: HashMap<String, serde_json::Value>,
+    }
+
+    #[cfg(feature = "precompile")]
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    pub struct Wifi {
+        #[flat_regex(regex = r"^wifi_\d+$", precompile)]
+        pub wifi: HashMap<String, bool>,
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    pub enum Device {
+        Switch {
+            #[flat_regex(regex = r"^port_\d+$", value_into)]
+            ports: HashMap<String, Box<str>>,
+        },
+    }
+}
+
+#[test]
+fn forbid_unsafe() {
+    let json = r#"{"lanport_0": true, "led_0": true, "led_1": false, "mtu_0": 1500, "speed_0": 100, "vlan_1": 1, "vrf_1": 2, "wifi_0": true, "x": 0}"#;
+    let router: forbid_unsafe::Router = serde_json::from_str(json).unwrap();
+    assert_eq!(router.lan.len(), 1);
+    assert_eq!(router.leds, [true, false]);
+    assert_eq!(router.mtu, [1500]);
+    assert_eq!(router.speeds.len(), 1);
+    assert_eq!(router.wan, None);
+    assert_eq!((router.routes.0.len(), router.routes.1.len()), (1, 1));
+    assert_eq!(router.rest.len(), 2);
+
+    let device: forbid_unsafe::Device =
+        serde_json::from_str(r#"{"Switch": {"port_0": "eth0"}}"#).unwrap();
+    let forbid_unsafe::Device::Switch { ports } = device;
+    assert_eq!(&*ports["port_0"], "eth0");
+
+    #[cfg(feature = "precompile")]
+    {
+        let wifi: forbid_unsafe::Wifi = serde_json::from_str(json).unwrap();
+        assert_eq!(wifi.wifi.len(), 1);
+    }
+}
+
+#[test]
+fn auto_prefix() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(regex = r"^lanport_\d+$", auto_prefix)]
+        lanports: HashMap<String, bool>,
+        #[flat_regex(rest)]
+        rest: HashMap<String, bool>,
+    }
+
+    let json = r#"{
+        "routerstatus_lanport_0": true,
+        "router_status_lanport_1": false,
+        "lanport_2": true,
+        "routerstatus_wanport_0": true
+    }"#;
+    let status: RouterStatus = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        status.lanports,
+        HashMap::from([
+            ("lanport_0".to_string(), true),
+            ("lanport_1".to_string(), false)
+        ])
+    );
+    // `rest` excludes the keys `lanports` takes after stripping the prefix
+    let mut rest = status.rest.keys().collect::<Vec<_>>();
+    rest.sort();
+    assert_eq!(rest, ["lanport_2", "routerstatus_wanport_0"]);
+}
+
+/// Orders keys by their text with embedded numbers compared numerically, `port_2` before `port_10`.
+#[derive(Debug, PartialEq, Eq)]
+struct NaturalKey(String);
+
+impl NaturalKey {
+    fn new(key: String) -> Self {
+        NaturalKey(key)
+    }
+
+    fn parts(&self) -> (&str, u64) {
+        let digits = self.0.trim_end_matches(|c: char| c.is_ascii_digit());
+        (digits, self.0[digits.len()..].parse().unwrap_or(0))
+    }
+}
+
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.parts().cmp(&other.parts())
+    }
+}
+
+#[test]
+fn key_wrap() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^port_\d+$", key_wrap = "NaturalKey::new")]
+        ports: BTreeMap<NaturalKey, bool>,
+    }
+
+    let json = r#"{"port_10": true, "port_2": false, "port_1": true, "speed": 1}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        ports
+            .ports
+            .keys()
+            .map(|key| key.0.as_str())
+            .collect::<Vec<_>>(),
+        ["port_1", "port_2", "port_10"]
+    );
+}
+
+mod maps {
+    pub type PortMap<K, V> = std::collections::BTreeMap<K, V>;
+}
+
+#[test]
+fn collection_paths() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^lanport_\d+$")]
+        lan: crate::maps::PortMap<String, bool>,
+        #[flat_regex(regex = r"^wanport_\d+$", reserve_hint)]
+        wan: std::collections::hash_map::HashMap<String, bool>,
+        #[flat_regex(regex = r"^wifi_\d+$")]
+        wifi: serde_json::Map<String, serde_json::Value>,
+    }
+
+    let json = r#"{"lanport_0": true, "wanport_0": false, "wifi_0": {"ssid": "home"}, "x": 1}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.lan.len(), 1);
+    assert_eq!(ports.wan.len(), 1);
+    assert_eq!(ports.wifi["wifi_0"]["ssid"], "home");
+}
+
+#[test]
+fn lossy_utf8() {
+    use std::ffi::CString;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ports(#[flat_regex(regex = r"^lanport_.\d$", lossy_utf8)] HashMap<CString, bool>);
+
+    let entries: Vec<(&[u8], bool)> = vec![
+        (b"lanport_\xff0", true),
+        (b"lanport_a1", false),
+        (b"wanport_\xff0", true),
+    ];
+    let ports: Ports = serde::Deserialize::deserialize(serde::de::value::MapDeserializer::<
+        _,
+        serde::de::value::Error,
+    >::new(entries.into_iter()))
+    .unwrap();
+    assert_eq!(ports.0.len(), 2);
+    // the original key is stored, not the lossy conversion
+    assert!(ports.0[&CString::new(&b"lanport_\xff0"[..]).unwrap()]);
+}
+
+#[test]
+fn cfg_feature() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize, Serialize)]
+    struct RouterStatus {
+        online: bool,
+        #[flat_regex(
+            regex = r"^lanportstatus_\d+$",
+            serialize_matching,
+            cfg_feature = "json"
+        )]
+        lanport_status: HashMap<String, bool>,
+    }
+
+    if cfg!(feature = "json") {
+        let json = serde_json::json!({"online": true, "lanportstatus_0": true});
+        let status: RouterStatus = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(status.lanport_status.len(), 1);
+        assert_eq!(serde_json::to_value(&status).unwrap(), json);
+    } else {
+        // a normal field without the feature
+        let json = serde_json::json!({"online": true, "lanport_status": {"lanportstatus_0": true}});
+        let status: RouterStatus = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(status.lanport_status.len(), 1);
+        assert_eq!(serde_json::to_value(&status).unwrap(), json);
+    }
+}
+
+#[test]
+fn glob() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(glob = "lanportstatus_*")]
+        status: HashMap<String, bool>,
+        #[flat_regex(glob = "port?.speed")]
+        speed: HashMap<String, u32>,
+        #[flat_regex(glob = "wifi_[0-9]")]
+        wifi: HashMap<String, bool>,
+        #[flat_regex(glob = "vlan_[!0-9]")]
+        vlans: HashMap<String, bool>,
+    }
+
+    let json = r#"{
+        "lanportstatus_0": true,
+        "lanportstatus_wan": false,
+        "xlanportstatus_1": true,
+        "port1.speed": 100,
+        "port12.speed": 10,
+        "port1xspeed": 1,
+        "wifi_0": true,
+        "wifi_a": true,
+        "vlan_a": true,
+        "vlan_1": true
+    }"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.status.len(), 2);
+    assert_eq!(
+        ports.speed,
+        HashMap::from([("port1.speed".to_string(), 100)])
+    );
+    assert_eq!(ports.wifi.keys().collect::<Vec<_>>(), ["wifi_0"]);
+    assert_eq!(ports.vlans.keys().collect::<Vec<_>>(), ["vlan_a"]);
+    assert_eq!(
+        Ports::FLAT_REGEX_PATTERNS[1],
+        ("speed", Some(r"^port.\.speed$"))
+    );
+}
+
+#[test]
+fn owned_keys_borrowed_values() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Hosts<'a> {
+        id: u32,
+        #[flat_regex(regex = r"^host_\d+$")]
+        #[serde(borrow)]
+        hosts: HashMap<String, &'a str>,
+    }
+
+    let json = String::from(
+        r#"{"id": 1, "host_0": "10.0.0.1", "host_1": "10.0.0.2", "gateway": "10.0.0.254"}"#,
+    );
+    let hosts: Hosts = serde_json::from_str(&json).unwrap();
+    assert_eq!(hosts.id, 1);
+    assert_eq!(hosts.hosts.len(), 2);
+    let input = json.as_bytes().as_ptr_range();
+    for value in hosts.hosts.values() {
+        assert!(
+            input.contains(&value.as_ptr()),
+            "{value} isn't borrowed from the input"
+        );
+    }
+    assert_eq!(hosts.hosts["host_1"], "10.0.0.2");
+}
+
+fn slot_port(key: (u8, u8)) -> String {
+    format!("{}/{}", key.0, key.1)
+}
+
+#[test]
+fn composite_keys() {
+    use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ports(
+        #[flat_regex(regex = r"^0/\d+$", key_access_owned = "slot_port")] HashMap<(u8, u8), bool>,
+    );
+
+    let key = |slot: u8, port: u8| SeqDeserializer::<_, Error>::new([slot, port].into_iter());
+    let entries = vec![(key(0, 1), true), (key(0, 2), false), (key(1, 1), true)];
+    let ports: Ports =
+        Deserialize::deserialize(MapDeserializer::<_, Error>::new(entries.into_iter())).unwrap();
+    assert_eq!(ports.0, HashMap::from([((0, 1), true), ((0, 2), false)]));
+}
+
+#[test]
+fn stop_key() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Frame {
+        #[flat_regex(regex = r"^port_\d+$", stop_key = "__end__")]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = r"^port_\d+$", stop_key = "__end__", borrow_key)]
+        borrowed: HashMap<String, bool>,
+    }
+
+    let json =
+        r#"{"port_0": true, "port_1": false, "__end__": null, "port_2": true, "garbage": [1, 2]}"#;
+    let frame: Frame = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        frame.ports,
+        HashMap::from([("port_0".to_string(), true), ("port_1".to_string(), false)])
+    );
+    assert_eq!(frame.borrowed, frame.ports);
+}
+
+#[test]
+fn size_limits() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(
+            regex = r"^port_\d+$",
+            size_limit = 1_000_000,
+            dfa_size_limit = 1_000_000
+        )]
+        ports: HashMap<String, bool>,
+        #[flat_regex(regex = concat!("^lan", r"_\d+$"), dfa_size_limit = 4096)]
+        lans: HashMap<String, bool>,
+    }
+
+    let json = r#"{"port_0": true, "lan_0": false, "wan_0": true}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.ports.len(), 1);
+    assert_eq!(ports.lans.len(), 1);
+}
+
+#[test]
+fn auto_borrow() {
+    use std::borrow::Cow;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports<'a> {
+        #[flat_regex(regex = r"^port_\d+$", auto_borrow)]
+        names: HashMap<String, &'a str>,
+        // a user provided `#[serde(borrow)]` isn't repeated
+        #[serde(borrow)]
+        #[flat_regex(regex = r"^port_\d+$", auto_borrow)]
+        cows: HashMap<&'a str, Cow<'a, str>>,
+        #[flat_regex(regex = r"^speed_\d+$", auto_borrow)]
+        speeds: HashMap<String, u32>,
+    }
+
+    let json = r#"{"port_0": "eth0", "port_1": "eth1", "speed_0": 100}"#;
+    let ports: Ports = serde_json::from_str(json).unwrap();
+    assert_eq!(ports.names["port_0"], "eth0");
+    assert_eq!(ports.cows["port_1"], "eth1");
+    assert_eq!(ports.speeds["speed_0"], 100);
+}
+
+#[test]
+fn empty_map() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Status {
+        #[flat_regex(regex = r"^lanportstatus_\d+$")]
+        status: HashMap<String, bool>,
+        #[flat_regex(regex = r"^lanportspeed_\d+$")]
+        speed: BTreeMap<String, u16>,
+        #[flat_regex(regex = r"^host_\w+$")]
+        hosts: Vec<(String, String)>,
+        #[flat_regex(regex = r"^vlan_(\d+)_\d+$", multimap, replace = "port_$1")]
+        vlans: HashMap<String, Vec<u16>>,
+        #[flat_regex(
+            regex = r"^mtu_(?P<idx>\d+)$",
+            into_indexed_vec,
+            key_from_capture = "idx",
+            contiguous
+        )]
+        mtu: Vec<u32>,
+        #[flat_regex(
+            regex = r"^led_(?P<idx>\d+)$",
+            array,
+            key_from_capture = "idx",
+            allow_missing
+        )]
+        leds: [bool; 2],
+    }
+
+    fn assert_empty(status: Status) {
+        assert!(status.status.is_empty());
+        assert!(status.speed.is_empty());
+        assert!(status.hosts.is_empty());
+        assert!(status.vlans.is_empty());
+        assert!(status.mtu.is_empty());
+        assert_eq!(status.leds, [false, false]);
+    }
+
+    assert_empty(serde_json::from_str("{}").unwrap());
+    assert_empty(serde_json::from_value(serde_json::json!({})).unwrap());
+    assert_empty(toml::from_str("").unwrap());
+    assert_empty(bson::from_document(bson::doc! {}).unwrap());
+    assert_empty(serde_flat_regex::from_value_strict(serde_json::json!({})).unwrap());
+    // only non-matching keys are the same as an empty map
+    assert_empty(serde_json::from_str(r#"{"online": true}"#).unwrap());
+
+    // an array without `allow_missing` still needs all its indices
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Leds {
+        #[flat_regex(regex = r"^led_(?P<idx>\d+)$", array, key_from_capture = "idx")]
+        leds: [bool; 2],
+    }
+    assert!(serde_json::from_str::<Leds>("{}").is_err());
+    let leds: Leds = serde_json::from_str(r#"{"led_0": true, "led_1": false}"#).unwrap();
+    assert_eq!(leds.leds, [true, false]);
+}
+
+#[test]
+fn absent_nested_map() {
+    #[flat_regex]
+    #[derive(Debug, Default, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^port_\d+$")]
+        ports: HashMap<String, bool>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        name: String,
+        #[serde(default)]
+        lan: Ports,
+        wan: Option<Ports>,
+    }
+
+    let router: Router = serde_json::from_str(r#"{"name": "r1"}"#).unwrap();
+    assert_eq!(router.name, "r1");
+    assert!(router.lan.ports.is_empty());
+    assert!(router.wan.is_none());
+
+    let router: Router = serde_json::from_str(r#"{"name": "r1", "lan": {}, "wan": {}}"#).unwrap();
+    assert!(router.lan.ports.is_empty());
+    assert!(router.wan.unwrap().ports.is_empty());
+
+    let router: Router = toml::from_str("name = \"r1\"\n[wan]\nport_0 = true\n").unwrap();
+    assert!(router.lan.ports.is_empty());
+    assert_eq!(
+        router.wan.unwrap().ports,
+        HashMap::from([("port_0".to_string(), true)])
+    );
+}
+
+#[cfg(feature = "precompile")]
+#[test]
+fn precompile() {
+    #[flat_regex]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(regex = r"(?i)^lanportstatus_\d+$", precompile, serialize_matching)]
+        lanport_status: HashMap<String, bool>,
+        #[flat_regex(prefix = "wan_", regex = r"\d+", suffix = "_up", precompile)]
+        wan: HashMap<String, bool>,
+        #[flat_regex(glob = "wifi_[0-9]", precompile)]
+        wifi: HashMap<String, u8>,
+    }
+
+    let json = r#"{"lanportstatus_0": true, "LanPortStatus_1": false, "lanportstatus_x": 1, "wan_0_up": true, "wan_0": 1, "wifi_0": 0}"#;
+    let status: RouterStatus = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        status.lanport_status,
+        HashMap::from([
+            ("lanportstatus_0".to_string(), true),
+            ("LanPortStatus_1".to_string(), false)
+        ])
+    );
+    assert_eq!(status.wan, HashMap::from([("wan_0_up".to_string(), true)]));
+    assert_eq!(status.wifi, HashMap::from([("wifi_0".to_string(), 0)]));
+
+    let mut status = status;
+    status.lanport_status.insert("speed_0".to_string(), true);
+    let value = serde_json::to_value(&status).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({"lanportstatus_0": true, "LanPortStatus_1": false, "wan_0_up": true, "wifi_0": 0})
+    );
+}
+
+#[cfg(feature = "serde_with")]
+#[test]
+fn value_serde_as() {
+    use serde_with::{serde_as, DisplayFromStr};
+    use std::net::Ipv4Addr;
+
+    #[serde_as]
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Hosts {
+        // `serde_as` still applies to the other fields
+        #[serde_as(as = "DisplayFromStr")]
+        port: u16,
+        #[flat_regex(regex = r"^host_\w+$", value_serde_as = "DisplayFromStr")]
+        hosts: HashMap<String, Ipv4Addr>,
+        #[flat_regex(
+            regex = r"^speed_\d+$",
+            value_serde_as = "DisplayFromStr",
+            on_error = "skip"
+        )]
+        speeds: HashMap<String, u32>,
+    }
+
+    let json = r#"{"port": "80", "host_a": "10.0.0.1", "speed_0": "100", "speed_1": "fast"}"#;
+    let hosts: Hosts = serde_json::from_str(json).unwrap();
+    assert_eq!(hosts.port, 80);
+    assert_eq!(
+        hosts.hosts,
+        HashMap::from([("host_a".to_string(), Ipv4Addr::new(10, 0, 0, 1))])
+    );
+    assert_eq!(hosts.speeds, HashMap::from([("speed_0".to_string(), 100)]));
+
+    let err = serde_json::from_str::<Hosts>(r#"{"port": "80", "host_a": "10.0.0"}"#).unwrap_err();
+    assert!(
+        err.to_string().starts_with("invalid IPv4 address syntax"),
+        "{err}"
+    );
+}
+
+#[test]
+fn option_field() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Default, PartialEq, Deserialize)]
+    #[serde(transparent)]
+    struct Lan(#[flat_regex(regex = r"^port_\d+$")] Option<HashMap<String, bool>>);
+
+    #[flat_regex]
+    #[derive(Debug, Default, PartialEq, Deserialize)]
+    #[serde(transparent)]
+    struct Wan(#[flat_regex(regex = r"^port_\d+$", empty_is_none)] Option<HashMap<String, bool>>);
+
+    #[derive(Debug, Deserialize)]
+    struct Router {
+        #[serde(default)]
+        lan: Lan,
+        #[serde(default)]
+        wan: Wan,
+    }
+
+    let ports = || Some(HashMap::from([("port_0".to_string(), true)]));
+    // absent
+    let router: Router = serde_json::from_str("{}").unwrap();
+    assert_eq!((router.lan.0, router.wan.0), (None, None));
+    // null
+    let router: Router = serde_json::from_str(r#"{"lan": null, "wan": null}"#).unwrap();
+    assert_eq!((router.lan.0, router.wan.0), (None, None));
+    // present without matching keys
+    let router: Router = serde_json::from_str(r#"{"lan": {"x": 1}, "wan": {}}"#).unwrap();
+    assert_eq!((router.lan.0, router.wan.0), (Some(HashMap::new()), None));
+    // present with matching keys
+    let json = r#"{"lan": {"port_0": true, "x": 1}, "wan": {"port_0": true}}"#;
+    let router: Router = serde_json::from_str(json).unwrap();
+    assert_eq!((router.lan.0, router.wan.0), (ports(), ports()));
+
+    // a flattened field always gets the map, only `empty_is_none` makes it `None`
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Status {
+        #[flat_regex(regex = r"^port_\d+$")]
+        lan: Option<HashMap<String, bool>>,
+        #[flat_regex(regex = r"^wan_\d+$", empty_is_none)]
+        wan: Option<BTreeMap<String, bool>>,
+    }
+    let status: Status = serde_json::from_str(r#"{"x": 1}"#).unwrap();
+    assert_eq!((status.lan, status.wan), (Some(HashMap::new()), None));
+    let status: Status = serde_json::from_str(r#"{"port_0": true, "wan_0": false}"#).unwrap();
+    assert_eq!(status.lan, ports());
+    assert_eq!(
+        status.wan,
+        Some(BTreeMap::from([("wan_0".to_string(), false)]))
+    );
+}
+
+#[test]
+// serde's wrapper of `deserialize_with` repeats the tuple type
+#[allow(clippy::type_complexity)]
+fn routes() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RouterStatus {
+        online: bool,
+        #[flat_regex(routes = [r"^lanport\w*_\d+$", r"^wanport_\d+$", r"^\w+port_\d+$"])]
+        ports: (
+            BTreeMap<String, bool>,
+            Vec<(String, bool)>,
+            HashMap<String, bool>,
+        ),
+    }
+
+    let json = r#"{"online": true, "lanport_0": true, "lanportstatus_1": false, "wanport_0": true, "wifiport_0": false, "speed_0": 1}"#;
+    let status: RouterStatus = serde_json::from_str(json).unwrap();
+    assert!(status.online);
+    let (lan, wan, other) = &status.ports;
+    assert_eq!(
+        lan,
+        &BTreeMap::from([
+            ("lanport_0".to_string(), true),
+            ("lanportstatus_1".to_string(), false)
+        ])
+    );
+    assert_eq!(wan, &[("wanport_0".to_string(), true)]);
+    // `wanport_0` also matches the last pattern, the first matching route wins
+    assert_eq!(other, &HashMap::from([("wifiport_0".to_string(), false)]));
+    assert_eq!(RouterStatus::FLAT_REGEX_PATTERNS.len(), 3);
+
+    let value = serde_json::to_value(&status).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({"online": true, "lanport_0": true, "lanportstatus_1": false, "wanport_0": true, "wifiport_0": false})
+    );
+
+    let err =
+        serde_json::from_str::<RouterStatus>(r#"{"online": true, "wanport_0": 1}"#).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("invalid type: integer `1`, expected a boolean"),
+        "{err}"
+    );
+}
+
+#[test]
+fn value_flat_regex() {
+    use std::collections::BTreeMap;
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Counters {
+        #[flat_regex(regex = r"^port_\d+$", value_flat_regex = r"^rx_\w+$")]
+        rx: HashMap<String, BTreeMap<String, u64>>,
+        #[flat_regex(regex = r"^wan_\d+$", value_flat_regex = r"^tx_\w+$")]
+        tx: BTreeMap<String, Vec<(String, u64)>>,
+    }
+
+    let json = r#"{
+        "port_0": {"rx_bytes": 10, "rx_drops": 1, "tx_bytes": 20, "link": "up"},
+        "port_1": {},
+        "wan_0": {"tx_packets": 3, "tx_bytes": 30, "rx_bytes": 1},
+        "uptime": 100
+    }"#;
+    let counters: Counters = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        counters.rx["port_0"],
+        BTreeMap::from([("rx_bytes".to_string(), 10), ("rx_drops".to_string(), 1)])
+    );
+    assert!(counters.rx["port_1"].is_empty());
+    assert_eq!(
+        counters.tx["wan_0"],
+        [("tx_packets".to_string(), 3), ("tx_bytes".to_string(), 30)]
+    );
+
+    let err = serde_json::from_str::<Counters>(r#"{"port_0": 1}"#).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains(r"a map with keys matching `^rx_\w+$`"));
+}
+
+#[test]
+fn value_as_convert() {
+    use std::collections::BTreeMap;
+
+    fn parse_speed(speed: String) -> Result<u16, std::num::ParseIntError> {
+        speed.trim_end_matches("Mbit").parse()
+    }
+
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Speeds {
+        #[flat_regex(
+            regex = r"^speed_\d+$",
+            value_as = "String",
+            value_convert = "parse_speed"
+        )]
+        speeds: HashMap<String, u16>,
+        #[flat_regex(
+            regex = r"^mtu_\d+$",
+            value_as = "String",
+            value_convert = "parse_speed",
+            on_error = "skip"
+        )]
+        mtus: BTreeMap<String, u16>,
+    }
+
+    let json = r#"{"speed_0": "100Mbit", "speed_1": "1000", "mtu_0": "1500", "mtu_1": "jumbo", "mtu_2": 9000}"#;
+    let speeds: Speeds = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        speeds.speeds,
+        HashMap::from([("speed_0".to_string(), 100), ("speed_1".to_string(), 1000)])
+    );
+    assert_eq!(speeds.mtus, BTreeMap::from([("mtu_0".to_string(), 1500)]));
+
+    let err = serde_json::from_str::<Speeds>(r#"{"speed_0": "fast"}"#).unwrap_err();
+    assert!(
+        err.to_string().starts_with("invalid digit found in string"),
+        "{err}"
+    );
+    // the wire type is deserialized as it is, without coercion
+    assert!(serde_json::from_str::<Speeds>(r#"{"speed_0": 100}"#).is_err());
+}
+
+#[test]
+fn vec_of_pairs_keeps_input_order() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Rules {
+        id: u32,
+        #[flat_regex(regex = r"^rule_\w+$")]
+        rules: Vec<(String, u32)>,
+    }
+
+    let expected = [
+        ("rule_z".to_string(), 3),
+        ("rule_a".to_string(), 1),
+        ("rule_m".to_string(), 2),
+    ];
+    let json = r#"{"rule_z": 3, "id": 1, "rule_a": 1, "other": 0, "rule_m": 2}"#;
+    let rules: Rules = serde_json::from_str(json).unwrap();
+    assert_eq!(rules.id, 1);
+    assert_eq!(rules.rules, expected);
+
+    let doc = bson::doc! {"rule_z": 3, "id": 1, "rule_a": 1, "other": 0, "rule_m": 2};
+    let rules: Rules = bson::from_document(doc).unwrap();
+    assert_eq!(rules.rules, expected);
+}
+
+#[cfg(feature = "smallvec")]
+#[test]
+fn smallvec_of_pairs() {
+    use smallvec::SmallVec;
+
+    #[flat_regex]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Rules {
+        id: u32,
+        #[flat_regex(regex = r"^rule_\w+$", serialize_matching)]
+        rules: SmallVec<[(String, u32); 2]>,
+    }
+
+    let json = r#"{"rule_z": 3, "id": 1, "rule_a": 1, "other": 0}"#;
+    let rules: Rules = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        rules.rules.as_slice(),
+        [("rule_z".to_string(), 3), ("rule_a".to_string(), 1)]
+    );
+    // up to `N` entries are stored inline
+    assert!(!rules.rules.spilled());
+    assert_eq!(
+        serde_json::to_string(&rules).unwrap(),
+        r#"{"id":1,"rule_z":3,"rule_a":1}"#
+    );
+
+    let json = r#"{"rule_z": 3, "id": 1, "rule_a": 1, "rule_m": 2}"#;
+    let rules: Rules = serde_json::from_str(json).unwrap();
+    assert_eq!(rules.rules.len(), 3);
+    assert!(rules.rules.spilled());
+}
+
+#[test]
+fn regex_include() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(regex_include = "tests/patterns/port.re", export_pattern)]
+        lanport_status: HashMap<String, bool>,
+    }
+
+    assert_eq!(RouterStatus::LANPORT_STATUS_PATTERN, r"^lanportstatus_\d+$");
+    let json = r#"{"lanportstatus_0": true, "lanportstatus_1": false, "wanportstatus_0": true}"#;
+    let status: RouterStatus = serde_json::from_str(json).unwrap();
+    assert_eq!(status.lanport_status.len(), 2);
+    assert!(status.lanport_status["lanportstatus_0"]);
+}
+
+#[test]
+fn export_pattern() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct RouterStatus {
+        #[flat_regex(regex = r"^lanportstatus_\d+$", export_pattern)]
+        lanport_status: HashMap<String, bool>,
+        #[flat_regex(prefix = "wan_", regex = r"\d+", export_pattern)]
+        r#type: HashMap<String, bool>,
+    }
+
+    assert_eq!(RouterStatus::LANPORT_STATUS_PATTERN, r"^lanportstatus_\d+$");
+    assert_eq!(RouterStatus::TYPE_PATTERN, r"^wan_(?:\d+)$");
+    let re = regex::Regex::new(RouterStatus::LANPORT_STATUS_PATTERN).unwrap();
+    assert!(re.is_match("lanportstatus_0"));
+    let status: RouterStatus =
+        serde_json::from_str(r#"{"lanportstatus_0": true, "wan_1": false}"#).unwrap();
+    assert_eq!((status.lanport_status.len(), status.r#type.len()), (1, 1));
+}
+
+#[test]
+fn only() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    #[serde(transparent)]
+    struct Ports(#[flat_regex(regex = r"^port_\d+$", only)] HashMap<String, bool>);
+
+    let ports: Ports = serde_json::from_str(r#"{"port_0": true, "port_1": false}"#).unwrap();
+    assert_eq!(ports.0.len(), 2);
+
+    let json = r#"{"port_0": true, "stray": 1, "port_1": false, "other": 2}"#;
+    let err = serde_json::from_str::<Ports>(json).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("key `stray` doesn't match the pattern of `0`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn only_with_siblings() {
+    #[flat_regex]
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[flat_regex(regex = r"^a_\d+$")]
+        a: HashMap<String, bool>,
+        #[flat_regex(regex = r"^b_\d+$", only)]
+        b: HashMap<String, bool>,
+    }
+
+    // the keys of `a` aren't an error for `b`
+    let ports: Ports = serde_json::from_str(r#"{"a_0": true, "b_0": false}"#).unwrap();
+    assert_eq!((ports.a.len(), ports.b.len()), (1, 1));
+
+    let err = serde_json::from_str::<Ports>(r#"{"a_0": true, "c_0": false}"#).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("key `c_0` doesn't match the pattern of `b`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn should_fail_at_compliltime() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/fail/regex_fail.rs");
+    t.compile_fail("tests/fail/regex_span_fail.rs");
+    t.compile_fail("tests/fail/key_access_fail.rs");
+    t.compile_fail("tests/fail/tuple_struct_fail.rs");
+    t.compile_fail("tests/fail/replace_fail.rs");
+    t.compile_fail("tests/fail/deny_leading_wildcard_fail.rs");
+    t.compile_fail("tests/fail/affix_comment_fail.rs");
+    t.compile_fail("tests/fail/value_not_deserialize_fail.rs");
+    t.compile_fail("tests/fail/value_not_deserialize_visitor_fail.rs");
+    t.compile_fail("tests/fail/nested_matcher_fail.rs");
+    t.compile_fail("tests/fail/glob_fail.rs");
+    t.compile_fail("tests/fail/size_limit_fail.rs");
+    t.compile_fail("tests/fail/routes_fail.rs");
+    t.compile_fail("tests/fail/serde_with_fail.rs");
+    t.compile_fail("tests/fail/on_collision_fail.rs");
+    t.compile_fail("tests/fail/reserve_hint_fail.rs");
+    #[cfg(feature = "json")]
+    t.compile_fail("tests/fail/merge_from_value_fail.rs");
+    #[cfg(feature = "precompile")]
+    t.compile_fail("tests/fail/precompile_fail.rs");
+}
+
+#[test]
+fn should_compile() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/pass/serialize_only.rs");
+    t.pass("tests/pass/transparent.rs");
+    t.pass("tests/pass/deny_leading_wildcard.rs");
+    t.pass("tests/pass/doc.rs");
+    t.pass("tests/pass/own_patterns_const.rs");
 }
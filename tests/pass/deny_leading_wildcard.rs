@@ -0,0 +1,16 @@
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(serde::Deserialize)]
+struct Ports {
+    #[flat_regex(regex = r"^port_\d+.*$", deny_leading_wildcard)]
+    ports: std::collections::HashMap<String, bool>,
+    #[flat_regex(regex = r"speed_\d+$", deny_leading_wildcard)]
+    speeds: std::collections::HashMap<String, u32>,
+}
+
+fn main() {
+    let ports: Ports = serde_json::from_str(r#"{"port_0": true, "speed_0": 100}"#).unwrap();
+    assert_eq!(ports.ports.len(), 1);
+    assert_eq!(ports.speeds.len(), 1);
+}
@@ -0,0 +1,27 @@
+//! The `doc` option documents the fields, so `missing_docs` passes.
+#![deny(missing_docs)]
+
+use serde::Deserialize;
+use serde_flat_regex::flat_regex;
+use std::collections::HashMap;
+
+/// The ports of a router.
+#[flat_regex]
+#[derive(Debug, Deserialize)]
+pub struct Ports {
+    #[flat_regex(regex = r"^lanportstatus_\d+$", doc)]
+    pub statuses: HashMap<String, String>,
+    #[flat_regex(prefix = "lanportspeed_", regex = r"\d+", doc)]
+    pub speeds: HashMap<String, u32>,
+}
+
+fn main() {
+    let raw = r#"{
+        "lanportstatus_0": "UP",
+        "lanportspeed_0": 100
+    }"#;
+
+    let ports: Ports = serde_json::from_str(raw).unwrap();
+    assert_eq!(ports.statuses.len(), 1);
+    assert_eq!(ports.speeds.len(), 1);
+}
@@ -0,0 +1,19 @@
+use serde::Deserialize;
+use serde_flat_regex::flat_regex;
+
+// without flat_regex fields the struct can declare its own FLAT_REGEX_PATTERNS
+#[flat_regex]
+#[derive(Debug, Deserialize)]
+struct Foo {
+    id: u32,
+}
+
+impl Foo {
+    const FLAT_REGEX_PATTERNS: &'static [&'static str] = &["id"];
+}
+
+fn main() {
+    let foo: Foo = serde_json::from_str(r#"{"id": 1}"#).unwrap();
+    assert_eq!(foo.id, 1);
+    assert_eq!(Foo::FLAT_REGEX_PATTERNS, ["id"]);
+}
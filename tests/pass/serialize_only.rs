@@ -0,0 +1,25 @@
+use serde::Serialize;
+use serde_flat_regex::flat_regex;
+
+#[flat_regex]
+#[derive(Debug, Serialize)]
+struct Foo {
+    id: u32,
+    #[flat_regex(regex = r"lanport(status|speed)_\d+")]
+    rest: std::collections::BTreeMap<std::string::String, String>,
+}
+
+fn main() {
+    let foo = Foo {
+        id: 1,
+        rest: std::collections::BTreeMap::from([
+            ("lanportstatus_0".to_string(), "UP".to_string()),
+            ("othterfield".to_string(), "ASD".to_string()),
+        ]),
+    };
+
+    assert_eq!(
+        serde_json::to_string(&foo).unwrap(),
+        r#"{"id":1,"lanportstatus_0":"UP"}"#
+    );
+}
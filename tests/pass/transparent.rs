@@ -0,0 +1,36 @@
+use serde::Deserialize;
+use serde_flat_regex::flat_regex;
+use std::collections::HashMap;
+
+#[flat_regex]
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+struct Ports(#[flat_regex(regex = r"lanportstatus_\d+")] HashMap<String, String>);
+
+#[derive(Debug, Deserialize)]
+struct RouterStatus {
+    id: u32,
+    ports: Ports,
+}
+
+fn main() {
+    let raw = r#"{
+        "id": 1,
+        "ports": {
+            "lanportstatus_0": "UP",
+            "lanportspeed_0": "100",
+            "lanportstatus_1": "DOWN"
+        }
+    }"#;
+
+    let router_status: RouterStatus = serde_json::from_str(raw).unwrap();
+
+    assert_eq!(router_status.id, 1);
+    assert_eq!(
+        router_status.ports.0,
+        HashMap::from([
+            ("lanportstatus_0".to_string(), "UP".to_string()),
+            ("lanportstatus_1".to_string(), "DOWN".to_string())
+        ])
+    );
+}